@@ -593,6 +593,81 @@ mod test {
     fn check_main_block() {
         check_blockheader_serialization(&MAINBLOCK_HEX[..], MAINBLOCK_HASH);
     }
+
+    fn decode_header(header_raw: &[u8]) -> super::BlockHeader {
+        Deserializer::from(Cursor::new(header_raw))
+            .deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn header_hash_golden() {
+        let cases = &[
+            ("zero", super::HeaderHash::from([0; 32])),
+            ("genesis", GENESIS_HASH.parse().unwrap()),
+            ("main", MAINBLOCK_HASH.parse().unwrap()),
+        ];
+        chain_core::golden::check_deserialize(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/header_hash.txt"),
+            cases,
+        );
+    }
+
+    // the headers and blocks are not `PartialEq`, so they are not read back
+    #[test]
+    fn block_header_golden() {
+        let cases = &[
+            ("boundary", decode_header(&GENESISBLOCK_HEX[..])),
+            ("main", decode_header(&MAINBLOCK_HEX[..])),
+        ];
+        chain_core::golden::check_serialize(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/block_header.txt"),
+            cases,
+        );
+    }
+
+    #[test]
+    fn block_golden() {
+        use super::super::{boundary, normal, update};
+        use cbor_event::Value;
+        use std::collections::BTreeMap;
+
+        let extra = Value::Array(vec![Value::Object(BTreeMap::new())]);
+        let boundary = match decode_header(&GENESISBLOCK_HEX[..]) {
+            super::BlockHeader::BoundaryBlockHeader(header) => boundary::Block {
+                header,
+                body: boundary::Body {
+                    slot_leaders: vec![],
+                },
+                extra: extra.clone(),
+            },
+            _ => unreachable!(),
+        };
+        let main = match decode_header(&MAINBLOCK_HEX[..]) {
+            super::BlockHeader::MainBlockHeader(header) => normal::Block::new(
+                header,
+                normal::Body::new(
+                    normal::TxPayload::empty(),
+                    normal::SscPayload::fake(),
+                    normal::DlgPayload(Value::Array(vec![])),
+                    update::UpdatePayload {
+                        proposal: None,
+                        votes: vec![],
+                    },
+                ),
+                extra,
+            ),
+            _ => unreachable!(),
+        };
+        let cases = &[
+            ("boundary", super::Block::BoundaryBlock(boundary)),
+            ("main_empty", super::Block::MainBlock(main)),
+        ];
+        chain_core::golden::check_serialize(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/block.txt"),
+            cases,
+        );
+    }
 }
 
 #[cfg(test)]
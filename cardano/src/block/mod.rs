@@ -9,6 +9,7 @@ pub mod chain_state;
 pub mod date;
 pub mod normal; /* normal block related value */
 pub mod sign;
+pub mod time;
 pub mod types;
 pub mod update;
 pub mod verify;
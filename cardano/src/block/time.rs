@@ -0,0 +1,593 @@
+//! Slot arithmetic and conversion of block dates to wall clock time
+//!
+//! `BlockDate` and `EpochSlotId` only carry an epoch and a slot index;
+//! moving between slots, counting slots or converting to time needs the
+//! number of slots in an epoch and the slot duration, given by `EraParams`.
+//!
+//! When these parameters change over the life of the chain, `TimeFrame`
+//! keeps the parameters of each era and does the conversions across the
+//! era boundaries.
+//!
+//! A boundary block is at the same slot as the first slot of its epoch.
+//! The conversions are overflow safe: they return `None` instead of
+//! wrapping or panicking. `EraParams::new` panics on invalid parameters,
+//! while `EraParams::from_genesis` and `TimeFrame::add_era`, fed by the
+//! genesis file and the chain updates, report them as an error.
+
+use block::date::BlockDate;
+use block::types::{EpochId, EpochSlotId, SlotId};
+use config::GenesisData;
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// Time parameters of the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EraParams {
+    start_time: SystemTime,
+    slot_duration: Duration,
+    slots_per_epoch: u32,
+}
+
+impl EraParams {
+    /// create the parameters of a chain whose first slot (epoch 0,
+    /// slot 0) starts at `start_time`
+    ///
+    /// # Panics
+    ///
+    /// if `slots_per_epoch` is 0 or more than the number of `SlotId`s,
+    /// or if `slot_duration` is 0.
+    pub fn new(start_time: SystemTime, slot_duration: Duration, slots_per_epoch: u32) -> Self {
+        assert!(
+            EraParams::are_valid(slot_duration, slots_per_epoch),
+            "invalid era parameters"
+        );
+        EraParams {
+            start_time,
+            slot_duration,
+            slots_per_epoch,
+        }
+    }
+
+    /// parameters of the chain described by the genesis data, which
+    /// has `10 * k` slots per epoch
+    pub fn from_genesis(genesis: &GenesisData) -> Result<Self, TimeFrameError> {
+        let slots_per_epoch = genesis
+            .epoch_stability_depth
+            .checked_mul(10)
+            .filter(|n| *n <= u32::MAX as usize)
+            .ok_or(TimeFrameError::Overflow)? as u32;
+        if !EraParams::are_valid(genesis.slot_duration, slots_per_epoch) {
+            return Err(TimeFrameError::InvalidParams {
+                slot_duration: genesis.slot_duration,
+                slots_per_epoch,
+            });
+        }
+        Ok(EraParams::new(
+            genesis.start_time,
+            genesis.slot_duration,
+            slots_per_epoch,
+        ))
+    }
+
+    /// check the parameters accepted by `new`
+    pub fn are_valid(slot_duration: Duration, slots_per_epoch: u32) -> bool {
+        slots_per_epoch > 0
+            && slots_per_epoch <= SlotId::MAX as u32 + 1
+            && slot_duration > Duration::from_secs(0)
+    }
+
+    pub fn start_time(&self) -> SystemTime {
+        self.start_time
+    }
+
+    pub fn slot_duration(&self) -> Duration {
+        self.slot_duration
+    }
+
+    pub fn slots_per_epoch(&self) -> u32 {
+        self.slots_per_epoch
+    }
+
+    /// number of slots from the first slot of the chain to the date
+    ///
+    /// `None` if the slot index is not in the epoch.
+    pub fn slot_number(&self, date: &BlockDate) -> Option<u64> {
+        let (epoch, slotid) = date.epoch_and_slot();
+        let slotid = slotid.unwrap_or(0);
+        if slotid as u32 >= self.slots_per_epoch {
+            return None;
+        }
+        epoch
+            .checked_mul(self.slots_per_epoch as u64)?
+            .checked_add(slotid as u64)
+    }
+
+    /// date of the slot at the given number of slots from the first
+    /// slot of the chain
+    pub fn date_of_slot(&self, slot_number: u64) -> EpochSlotId {
+        let slots_per_epoch = self.slots_per_epoch as u64;
+        EpochSlotId {
+            epoch: slot_number / slots_per_epoch,
+            slotid: (slot_number % slots_per_epoch) as SlotId,
+        }
+    }
+
+    /// the slot following the date, in the next epoch after the last
+    /// slot of an epoch
+    ///
+    /// The slot following a boundary block is the first slot of its
+    /// epoch.
+    pub fn next_slot(&self, date: &BlockDate) -> Option<EpochSlotId> {
+        match date {
+            BlockDate::Boundary(epoch) => Some(EpochSlotId {
+                epoch: *epoch,
+                slotid: 0,
+            }),
+            BlockDate::Normal(_) => self
+                .slot_number(date)?
+                .checked_add(1)
+                .map(|n| self.date_of_slot(n)),
+        }
+    }
+
+    /// the slot preceding the date, `None` for the first slot of the
+    /// chain
+    pub fn previous_slot(&self, date: &BlockDate) -> Option<EpochSlotId> {
+        self.slot_number(date)?
+            .checked_sub(1)
+            .map(|n| self.date_of_slot(n))
+    }
+
+    /// number of slots from `from` to `to`, `None` if `to` is before
+    /// `from`
+    pub fn slots_until(&self, from: &BlockDate, to: &BlockDate) -> Option<u64> {
+        self.slot_number(to)?.checked_sub(self.slot_number(from)?)
+    }
+
+    /// time at which the slot of the date starts
+    pub fn slot_start_time(&self, date: &BlockDate) -> Option<SystemTime> {
+        let slot_number = self.slot_number(date)?;
+        let secs = self.slot_duration.as_secs().checked_mul(slot_number)?;
+        let nanos = (self.slot_duration.subsec_nanos() as u64).checked_mul(slot_number)?;
+        let elapsed = Duration::from_secs(secs).checked_add(Duration::from_nanos(nanos))?;
+        self.start_time.checked_add(elapsed)
+    }
+
+    /// the slot in progress at the given time, `None` if the time is
+    /// before the start of the chain
+    pub fn slot_at_time(&self, time: SystemTime) -> Option<EpochSlotId> {
+        let elapsed = time.duration_since(self.start_time).ok()?;
+        let slot_number = elapsed.as_nanos() / self.slot_duration.as_nanos();
+        if slot_number > u64::MAX as u128 {
+            return None;
+        }
+        Some(self.date_of_slot(slot_number as u64))
+    }
+}
+
+/// Error when adding an era to a `TimeFrame`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFrameError {
+    /// the era does not start after the start of the last era
+    EraNotIncreasing {
+        first_epoch: EpochId,
+        last_era: EpochId,
+    },
+    /// the slot duration is 0, or the number of slots per epoch is 0 or
+    /// more than the number of `SlotId`s
+    InvalidParams {
+        slot_duration: Duration,
+        slots_per_epoch: u32,
+    },
+    /// the start time of the era, or the number of slots per epoch of
+    /// the genesis data, cannot be represented
+    Overflow,
+}
+
+impl fmt::Display for TimeFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeFrameError::EraNotIncreasing {
+                first_epoch,
+                last_era,
+            } => write!(
+                f,
+                "era starting at epoch {} does not follow the era starting at epoch {}",
+                first_epoch, last_era
+            ),
+            TimeFrameError::InvalidParams {
+                slot_duration,
+                slots_per_epoch,
+            } => write!(
+                f,
+                "invalid era of {} slots per epoch lasting {:?}",
+                slots_per_epoch, slot_duration
+            ),
+            TimeFrameError::Overflow => write!(f, "era start time overflow"),
+        }
+    }
+}
+
+impl Error for TimeFrameError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Era {
+    first_epoch: EpochId,
+    /// number of slots of the time frame before this era
+    first_slot: u64,
+    /// parameters relative to the era: their epoch 0 is `first_epoch`
+    params: EraParams,
+}
+
+impl Era {
+    fn relative_date(&self, date: &BlockDate) -> BlockDate {
+        match date {
+            BlockDate::Boundary(epoch) => BlockDate::Boundary(epoch - self.first_epoch),
+            BlockDate::Normal(slot) => BlockDate::Normal(EpochSlotId {
+                epoch: slot.epoch - self.first_epoch,
+                slotid: slot.slotid,
+            }),
+        }
+    }
+
+    fn absolute_slot(&self, slot: EpochSlotId) -> Option<EpochSlotId> {
+        Some(EpochSlotId {
+            epoch: slot.epoch.checked_add(self.first_epoch)?,
+            slotid: slot.slotid,
+        })
+    }
+}
+
+/// Time parameters of a chain made of successive eras
+///
+/// Each era starts at the beginning of an epoch and has its own slot
+/// duration and number of slots per epoch, applying until the next era.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeFrame {
+    eras: Vec<Era>,
+}
+
+impl TimeFrame {
+    /// time frame whose first era starts at epoch 0 with the given
+    /// parameters
+    pub fn new(params: EraParams) -> Self {
+        TimeFrame {
+            eras: vec![Era {
+                first_epoch: 0,
+                first_slot: 0,
+                params,
+            }],
+        }
+    }
+
+    /// start a new era at the beginning of `first_epoch`, which must be
+    /// after the start of the last era, with valid parameters (see
+    /// `EraParams::new`)
+    pub fn add_era(
+        &mut self,
+        first_epoch: EpochId,
+        slot_duration: Duration,
+        slots_per_epoch: u32,
+    ) -> Result<(), TimeFrameError> {
+        if !EraParams::are_valid(slot_duration, slots_per_epoch) {
+            return Err(TimeFrameError::InvalidParams {
+                slot_duration,
+                slots_per_epoch,
+            });
+        }
+        let last = *self.eras.last().unwrap();
+        if first_epoch <= last.first_epoch {
+            return Err(TimeFrameError::EraNotIncreasing {
+                first_epoch,
+                last_era: last.first_epoch,
+            });
+        }
+        let era_start = BlockDate::Boundary(first_epoch - last.first_epoch);
+        let start_time = last
+            .params
+            .slot_start_time(&era_start)
+            .ok_or(TimeFrameError::Overflow)?;
+        let first_slot = last
+            .params
+            .slot_number(&era_start)
+            .and_then(|n| n.checked_add(last.first_slot))
+            .ok_or(TimeFrameError::Overflow)?;
+        self.eras.push(Era {
+            first_epoch,
+            first_slot,
+            params: EraParams::new(start_time, slot_duration, slots_per_epoch),
+        });
+        Ok(())
+    }
+
+    fn era_of_epoch(&self, epoch: EpochId) -> &Era {
+        self.eras
+            .iter()
+            .rev()
+            .find(|era| era.first_epoch <= epoch)
+            .unwrap()
+    }
+
+    /// parameters of the era the epoch belongs to; their start time is
+    /// the start of the era
+    pub fn era_params(&self, epoch: EpochId) -> &EraParams {
+        &self.era_of_epoch(epoch).params
+    }
+
+    /// number of slots from the first slot of the time frame to the date
+    pub fn slot_number(&self, date: &BlockDate) -> Option<u64> {
+        let era = self.era_of_epoch(date.get_epochid());
+        era.params
+            .slot_number(&era.relative_date(date))?
+            .checked_add(era.first_slot)
+    }
+
+    /// the slot following the date, see `EraParams::next_slot`
+    pub fn next_slot(&self, date: &BlockDate) -> Option<EpochSlotId> {
+        let era = self.era_of_epoch(date.get_epochid());
+        let slot = era.params.next_slot(&era.relative_date(date))?;
+        era.absolute_slot(slot)
+    }
+
+    /// the slot preceding the date, possibly in the previous era
+    pub fn previous_slot(&self, date: &BlockDate) -> Option<EpochSlotId> {
+        let epoch = date.get_epochid();
+        match date.slotid() {
+            Some(slotid) if slotid > 0 => {
+                let era = self.era_of_epoch(epoch);
+                let slot = era.params.previous_slot(&era.relative_date(date))?;
+                era.absolute_slot(slot)
+            }
+            _ => {
+                let previous_epoch = epoch.checked_sub(1)?;
+                let slots_per_epoch = self.era_params(previous_epoch).slots_per_epoch();
+                Some(EpochSlotId {
+                    epoch: previous_epoch,
+                    slotid: (slots_per_epoch - 1) as SlotId,
+                })
+            }
+        }
+    }
+
+    /// number of slots from `from` to `to`, `None` if `to` is before
+    /// `from`
+    pub fn slots_until(&self, from: &BlockDate, to: &BlockDate) -> Option<u64> {
+        self.slot_number(to)?.checked_sub(self.slot_number(from)?)
+    }
+
+    /// time at which the slot of the date starts
+    pub fn slot_start_time(&self, date: &BlockDate) -> Option<SystemTime> {
+        let era = self.era_of_epoch(date.get_epochid());
+        era.params.slot_start_time(&era.relative_date(date))
+    }
+
+    /// the slot in progress at the given time, `None` if the time is
+    /// before the start of the time frame
+    pub fn slot_at_time(&self, time: SystemTime) -> Option<EpochSlotId> {
+        let era = self
+            .eras
+            .iter()
+            .rev()
+            .find(|era| era.params.start_time() <= time)?;
+        let slot = era.params.slot_at_time(time)?;
+        era.absolute_slot(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::types::HeaderHash;
+    use config::ProtocolMagic;
+    use fee::LinearFee;
+    use std::collections::BTreeMap;
+    use std::time::UNIX_EPOCH;
+
+    fn params() -> EraParams {
+        EraParams::new(UNIX_EPOCH, Duration::from_secs(20), 100)
+    }
+
+    fn genesis(k: usize, slot_duration: Duration) -> GenesisData {
+        GenesisData {
+            genesis_prev: HeaderHash::from([0; 32]),
+            epoch_stability_depth: k,
+            start_time: UNIX_EPOCH,
+            slot_duration,
+            protocol_magic: ProtocolMagic::default(),
+            fee_policy: LinearFee::default(),
+            avvm_distr: BTreeMap::new(),
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+        }
+    }
+
+    fn normal(epoch: EpochId, slotid: SlotId) -> BlockDate {
+        BlockDate::Normal(EpochSlotId { epoch, slotid })
+    }
+
+    #[test]
+    fn params_from_genesis() {
+        let slot = Duration::from_secs(20);
+        assert_eq!(
+            EraParams::from_genesis(&genesis(2160, slot)),
+            Ok(EraParams::new(UNIX_EPOCH, slot, 21600))
+        );
+        for (k, slot_duration, slots_per_epoch) in &[
+            (0, slot, 0),
+            (6554, slot, 65540),
+            (2160, Duration::from_secs(0), 21600),
+        ] {
+            assert_eq!(
+                EraParams::from_genesis(&genesis(*k, *slot_duration)),
+                Err(TimeFrameError::InvalidParams {
+                    slot_duration: *slot_duration,
+                    slots_per_epoch: *slots_per_epoch
+                })
+            );
+        }
+        assert_eq!(
+            EraParams::from_genesis(&genesis(usize::MAX / 5, slot)),
+            Err(TimeFrameError::Overflow)
+        );
+        assert_eq!(
+            EraParams::from_genesis(&genesis(u32::MAX as usize, slot)),
+            Err(TimeFrameError::Overflow)
+        );
+    }
+
+    #[test]
+    fn slot_arithmetic() {
+        let params = params();
+        assert_eq!(params.slot_number(&normal(2, 5)), Some(205));
+        assert_eq!(params.slot_number(&BlockDate::Boundary(2)), Some(200));
+        assert_eq!(params.slot_number(&normal(2, 100)), None);
+        assert_eq!(params.slot_number(&normal(u64::MAX, 0)), None);
+
+        assert_eq!(
+            params.next_slot(&normal(2, 5)),
+            Some(EpochSlotId {
+                epoch: 2,
+                slotid: 6
+            })
+        );
+        assert_eq!(
+            params.next_slot(&normal(2, 99)),
+            Some(EpochSlotId {
+                epoch: 3,
+                slotid: 0
+            })
+        );
+        assert_eq!(
+            params.next_slot(&BlockDate::Boundary(3)),
+            Some(EpochSlotId {
+                epoch: 3,
+                slotid: 0
+            })
+        );
+        assert_eq!(
+            params.previous_slot(&normal(3, 0)),
+            Some(EpochSlotId {
+                epoch: 2,
+                slotid: 99
+            })
+        );
+        assert_eq!(params.previous_slot(&BlockDate::Boundary(0)), None);
+
+        assert_eq!(
+            params.slots_until(&normal(1, 50), &normal(3, 10)),
+            Some(160)
+        );
+        assert_eq!(params.slots_until(&normal(3, 10), &normal(1, 50)), None);
+    }
+
+    #[test]
+    fn wall_clock() {
+        let params = params();
+        assert_eq!(params.slot_start_time(&normal(0, 0)), Some(UNIX_EPOCH));
+        assert_eq!(
+            params.slot_start_time(&normal(1, 3)),
+            Some(UNIX_EPOCH + Duration::from_secs(2060))
+        );
+        assert_eq!(
+            params.slot_at_time(UNIX_EPOCH + Duration::from_secs(2079)),
+            Some(EpochSlotId {
+                epoch: 1,
+                slotid: 3
+            })
+        );
+        assert_eq!(
+            params.slot_at_time(UNIX_EPOCH + Duration::from_secs(2080)),
+            Some(EpochSlotId {
+                epoch: 1,
+                slotid: 4
+            })
+        );
+        let before = EraParams::new(
+            UNIX_EPOCH + Duration::from_secs(10),
+            Duration::from_secs(1),
+            10,
+        );
+        assert_eq!(before.slot_at_time(UNIX_EPOCH), None);
+    }
+
+    #[test]
+    fn time_frame_eras() {
+        // 10 epochs of 100 slots of 20s, then 50 slots of 10s
+        let mut time_frame = TimeFrame::new(params());
+        time_frame.add_era(10, Duration::from_secs(10), 50).unwrap();
+        assert_eq!(
+            time_frame.add_era(10, Duration::from_secs(1), 10),
+            Err(TimeFrameError::EraNotIncreasing {
+                first_epoch: 10,
+                last_era: 10
+            })
+        );
+        for (slot_duration, slots_per_epoch) in &[
+            (Duration::from_secs(0), 10),
+            (Duration::from_secs(1), 0),
+            (Duration::from_secs(1), 65537),
+        ] {
+            assert_eq!(
+                time_frame.add_era(20, *slot_duration, *slots_per_epoch),
+                Err(TimeFrameError::InvalidParams {
+                    slot_duration: *slot_duration,
+                    slots_per_epoch: *slots_per_epoch
+                })
+            );
+        }
+        time_frame
+            .add_era(30, Duration::from_millis(1), 65536)
+            .unwrap();
+        assert_eq!(time_frame.era_params(29).slots_per_epoch(), 50);
+
+        let era_start = UNIX_EPOCH + Duration::from_secs(20_000);
+        assert_eq!(time_frame.era_params(12).start_time(), era_start);
+        assert_eq!(time_frame.era_params(9).slots_per_epoch(), 100);
+        assert_eq!(
+            time_frame.slot_start_time(&normal(9, 99)),
+            Some(era_start - Duration::from_secs(20))
+        );
+        assert_eq!(
+            time_frame.slot_start_time(&normal(11, 1)),
+            Some(era_start + Duration::from_secs(510))
+        );
+        assert_eq!(
+            time_frame.slot_at_time(era_start + Duration::from_secs(515)),
+            Some(EpochSlotId {
+                epoch: 11,
+                slotid: 1
+            })
+        );
+        assert_eq!(
+            time_frame.slot_at_time(era_start - Duration::from_secs(1)),
+            Some(EpochSlotId {
+                epoch: 9,
+                slotid: 99
+            })
+        );
+
+        assert_eq!(time_frame.slot_number(&normal(11, 1)), Some(1051));
+        assert_eq!(
+            time_frame.slots_until(&normal(9, 98), &normal(10, 2)),
+            Some(4)
+        );
+        assert_eq!(
+            time_frame.next_slot(&normal(10, 49)),
+            Some(EpochSlotId {
+                epoch: 11,
+                slotid: 0
+            })
+        );
+        assert_eq!(
+            time_frame.previous_slot(&BlockDate::Boundary(10)),
+            Some(EpochSlotId {
+                epoch: 9,
+                slotid: 99
+            })
+        );
+        assert_eq!(time_frame.slot_number(&normal(10, 50)), None);
+    }
+}
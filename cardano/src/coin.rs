@@ -11,6 +11,9 @@ use std::{fmt, io::BufRead, ops, result};
 /// maximum value of a Lovelace.
 pub const MAX_COIN: u64 = 45_000_000_000__000_000;
 
+/// number of Lovelace in one ada.
+pub const LOVELACE_PER_ADA: u64 = 1_000_000;
+
 /// error type relating to `Coin` operations
 ///
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -18,11 +21,16 @@ pub const MAX_COIN: u64 = 45_000_000_000__000_000;
 pub enum Error {
     /// means that the given value was out of bound
     ///
-    /// Max bound being: `MAX_COIN`.
+    /// Max bound being: `MAX_COIN`. A parsed amount too large for a `u64`
+    /// is reported as `u64::MAX`.
     OutOfBound(u64),
 
     ParseIntError,
 
+    /// an amount of ada with more than 6 decimals, which cannot be
+    /// represented in Lovelace
+    TooManyDecimals,
+
     Negative,
 }
 impl fmt::Display for Error {
@@ -34,6 +42,7 @@ impl fmt::Display for Error {
                 v, MAX_COIN
             ),
             &Error::ParseIntError => write!(f, "Cannot parse a valid integer"),
+            &Error::TooManyDecimals => write!(f, "Ada amount has more than 6 decimals"),
             &Error::Negative => write!(f, "Coin cannot hold a negative value"),
         }
     }
@@ -115,17 +124,79 @@ impl Coin {
 }
 impl fmt::Display for Coin {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.{:06}", self.0 / 1000000, self.0 % 1000000)
+        write!(
+            f,
+            "{}.{:06}",
+            self.0 / LOVELACE_PER_ADA,
+            self.0 % LOVELACE_PER_ADA
+        )
+    }
+}
+
+fn parse_u64(s: &str) -> Result<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::ParseIntError);
     }
+    // only the digits are left, so this can only fail on overflow
+    s.parse().map_err(|_| Error::OutOfBound(u64::MAX))
+}
+
+/// parse an amount of ada with up to 6 decimals into Lovelace
+fn parse_ada(s: &str) -> Result<Coin> {
+    let (integer, decimals) = match s.find('.') {
+        None => (s, None),
+        Some(pos) => (&s[..pos], Some(&s[(pos + 1)..])),
+    };
+    let ada = parse_u64(integer)?;
+    let lovelace = match decimals {
+        None => 0,
+        Some(decimals) if decimals.len() > 6 => return Err(Error::TooManyDecimals),
+        Some(decimals) => parse_u64(decimals)? * 10u64.pow(6 - decimals.len() as u32),
+    };
+    let value = ada
+        .checked_mul(LOVELACE_PER_ADA)
+        .and_then(|v| v.checked_add(lovelace))
+        .unwrap_or(u64::MAX);
+    Coin::new(value)
 }
+
+/// Parse an amount of Lovelace or ada
+///
+/// The accepted formats are:
+///
+/// * an integer, or an integer followed by ` lovelace`: an amount of
+///   Lovelace;
+/// * an integer or a decimal number with up to 6 decimals, followed by
+///   ` ada`: an amount of ada.
+///
+/// The ` ada` suffix is mandatory: a number without unit is always an
+/// integer amount of Lovelace, and a decimal number without unit (such as
+/// the output of `Display`) is rejected rather than read as ada.
+///
+/// # Example
+///
+/// ```
+/// use cardano::coin::{Coin};
+///
+/// let coin = Coin::new(12_000_001).unwrap();
+///
+/// assert_eq!("12000001".parse::<Coin>(), Ok(coin));
+/// assert_eq!("12000001 lovelace".parse::<Coin>(), Ok(coin));
+/// assert_eq!("12.000001 ada".parse::<Coin>(), Ok(coin));
+/// assert_eq!(format!("{} ada", coin).parse::<Coin>(), Ok(coin));
+/// assert!("12.000001".parse::<Coin>().is_err());
+/// assert!("12.0000001 ada".parse::<Coin>().is_err());
+/// ```
 impl ::std::str::FromStr for Coin {
     type Err = Error;
     fn from_str(s: &str) -> result::Result<Self, Self::Err> {
-        let v: u64 = match s.parse() {
-            Err(_) => return Err(Error::ParseIntError),
-            Ok(v) => v,
-        };
-        Coin::new(v)
+        if let Some(ada) = s.strip_suffix(" ada") {
+            parse_ada(ada)
+        } else if let Some(lovelace) = s.strip_suffix(" lovelace") {
+            Coin::new(parse_u64(lovelace)?)
+        } else {
+            Coin::new(parse_u64(s)?)
+        }
     }
 }
 impl cbor_event::se::Serialize for Coin {
@@ -212,12 +283,54 @@ mod test {
     use super::super::util::arbitrary::Wrapper;
     use super::*;
 
+    #[test]
+    fn parse_units() {
+        assert_eq!("0".parse(), Ok(Coin(0)));
+        assert_eq!("1 ada".parse(), Ok(Coin(1_000_000)));
+        assert_eq!("1.5 ada".parse(), Ok(Coin(1_500_000)));
+        assert_eq!("0.000001 ada".parse(), Ok(Coin(1)));
+        assert_eq!("45000000000 ada".parse(), Ok(Coin(MAX_COIN)));
+        assert_eq!(
+            "45000000000.000001 ada".parse::<Coin>(),
+            Err(Error::OutOfBound(MAX_COIN + 1))
+        );
+        for overflow in &[
+            "18446744073709551615 ada",
+            "18446744073709551616",
+            "18446744073709551616 lovelace",
+            "18446744073709551616.5 ada",
+        ] {
+            assert_eq!(overflow.parse::<Coin>(), Err(Error::OutOfBound(u64::MAX)));
+        }
+        assert_eq!("0.0000001 ada".parse::<Coin>(), Err(Error::TooManyDecimals));
+        for invalid in &[
+            "",
+            "ada",
+            " ada",
+            "1.5 lovelace",
+            "-1",
+            "+1",
+            "1.-5",
+            "1e6",
+            "1.5ada",
+            "12.0",
+            "0.000001",
+        ] {
+            assert_eq!(invalid.parse::<Coin>(), Err(Error::ParseIntError));
+        }
+    }
+
     quickcheck! {
         // test a given u32 is always a valid value for a `Coin`
         fn coin_from_u32_always_valid(v: u32) -> bool {
             Coin::new(v as u64).is_ok()
         }
 
+        // test the printed value can be parsed back as ada
+        fn coin_display_parse(coin: Wrapper<Coin>) -> bool {
+            format!("{} ada", *coin).parse::<Coin>() == Ok(*coin)
+        }
+
         // test the cbor serialization/deserialization
         fn coin_cbor_serialization(coin: Wrapper<Coin>) -> bool {
             let bytes = cbor!(*coin).unwrap();
@@ -953,6 +953,50 @@ mod tests {
 
         assert!(cbor_event::test_encode_decode(&txaux).expect("encode/decode TxAux"));
     }
+
+    fn golden_tx() -> Tx {
+        let seed = hdwallet::Seed::from_bytes(SEED);
+        let pk = hdwallet::XPrv::generate_from_seed(&seed).public();
+        let hdap = hdpayload::HDAddressPayload::from_bytes(HDPAYLOAD);
+        let attrs = address::Attributes::new_single_key(&pk, Some(hdap), NetworkMagic::NoMagic);
+        let ea = address::ExtendedAddr::new(
+            address::AddrType::ATPubKey,
+            address::SpendingData::PubKeyASD(pk),
+            attrs,
+        );
+
+        let mut tx = Tx::new();
+        tx.add_input(TxoPointer::new(TxId::new(&[0; 32]), 666));
+        tx.add_output(TxOut::new(ea, Coin::new(42).unwrap()));
+        tx
+    }
+
+    // the transactions are not read back: decoding their indefinite length
+    // arrays fails with this version of cbor_event, see `tx_decode`
+    #[test]
+    fn tx_golden() {
+        let cases = &[("empty", Tx::new()), ("one_input_one_output", golden_tx())];
+        chain_core::golden::check_serialize(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/tx.txt"),
+            cases,
+        );
+    }
+
+    #[test]
+    fn txaux_golden() {
+        let tx = golden_tx();
+        let seed = hdwallet::Seed::from_bytes(SEED);
+        let sk = hdwallet::XPrv::generate_from_seed(&seed);
+        let witness = TxInWitness::new_extended_pk(ProtocolMagic::default(), &sk, &tx.id());
+        let cases = &[(
+            "one_witness",
+            TxAux::new(tx, TxWitness::from(vec![witness])),
+        )];
+        chain_core::golden::check_serialize(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/tx_aux.txt"),
+            cases,
+        );
+    }
 }
 
 #[cfg(feature = "with-bench")]
@@ -0,0 +1,156 @@
+//! Derive macros for the `chain_core` serialization traits
+//!
+//! * `#[derive(Readable)]` implements `chain_core::mempack::Readable`;
+//! * `#[derive(Serialize)]` implements `chain_core::property::Serialize`
+//!   with `std::io::Error` as the error type.
+//!
+//! Both only apply to structs: the fields are read and written one after
+//! the other, in the order of their declaration, with the codecs of
+//! their own types. So the two derived implementations of a struct are
+//! always consistent with each other.
+//!
+//! ```
+//! use chain_core::mempack::{ReadBuf, Readable};
+//! use chain_core::property::Serialize;
+//! use chain_core_derive::{Readable, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Readable, Serialize)]
+//! struct Pointer {
+//!     id: [u8; 4],
+//!     index: u16,
+//! }
+//!
+//! let pointer = Pointer { id: [1, 2, 3, 4], index: 5 };
+//! let bytes = pointer.serialize_as_vec().unwrap();
+//! assert_eq!(bytes, vec![1, 2, 3, 4, 0, 5]);
+//! assert_eq!(Pointer::read(&mut ReadBuf::from(&bytes)).unwrap(), pointer);
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+/// the fields of the struct, or a compile error for other items
+fn struct_fields(input: &DeriveInput, derive: &str) -> Result<Fields, TokenStream2> {
+    match &input.data {
+        Data::Struct(data) => Ok(data.fields.clone()),
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            format!("#[derive({})] is only supported for structs", derive),
+        )
+        .to_compile_error()),
+    }
+}
+
+/// the expressions accessing the fields of `self`, in declaration order
+fn field_accessors(fields: &Fields) -> Vec<TokenStream2> {
+    match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                quote! { self.#ident }
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote! { self.#index }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    }
+}
+
+#[proc_macro_derive(Readable)]
+pub fn derive_readable(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input, "Readable") {
+        Ok(fields) => fields,
+        Err(err) => return err.into(),
+    };
+
+    let where_clause = input.generics.make_where_clause();
+    for field in fields.iter() {
+        let ty = &field.ty;
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::chain_core::mempack::Readable));
+    }
+
+    let read = quote! { ::chain_core::mempack::Readable::read(buf)? };
+    let body = match &fields {
+        Fields::Named(named) => {
+            let reads = named.named.iter().map(|field| {
+                let ident = &field.ident;
+                quote! { #ident: #read }
+            });
+            quote! { Self { #(#reads,)* } }
+        }
+        Fields::Unnamed(unnamed) => {
+            let reads = unnamed.unnamed.iter().map(|_| &read);
+            quote! { Self(#(#reads,)*) }
+        }
+        Fields::Unit => quote! { Self },
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::chain_core::mempack::Readable for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn read<'derive_buf>(
+                buf: &mut ::chain_core::mempack::ReadBuf<'derive_buf>,
+            ) -> ::core::result::Result<Self, ::chain_core::mempack::ReadError> {
+                ::core::result::Result::Ok(#body)
+            }
+        }
+    };
+    expanded.into()
+}
+
+#[proc_macro_derive(Serialize)]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let fields = match struct_fields(&input, "Serialize") {
+        Ok(fields) => fields,
+        Err(err) => return err.into(),
+    };
+
+    let where_clause = input.generics.make_where_clause();
+    for field in fields.iter() {
+        let ty = &field.ty;
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::chain_core::property::Serialize));
+        where_clause.predicates.push(parse_quote!(
+            <#ty as ::chain_core::property::Serialize>::Error: ::core::convert::Into<::std::io::Error>
+        ));
+    }
+
+    let accessors = field_accessors(&fields);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::chain_core::property::Serialize for #name #ty_generics #where_clause {
+            type Error = ::std::io::Error;
+
+            #[allow(unused_mut, unused_variables)]
+            fn serialize<W: ::std::io::Write>(
+                &self,
+                mut writer: W,
+            ) -> ::core::result::Result<(), Self::Error> {
+                #(
+                    ::chain_core::property::Serialize::serialize(&#accessors, &mut writer)
+                        .map_err(::core::convert::Into::<::std::io::Error>::into)?;
+                )*
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+    expanded.into()
+}
@@ -0,0 +1,67 @@
+use chain_core::mempack::{ReadBuf, ReadError, Readable};
+use chain_core::property::Serialize;
+use chain_core_derive::{Readable, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Readable, Serialize)]
+struct Named {
+    tag: u8,
+    value: u64,
+    hash: [u8; 4],
+}
+
+#[derive(Debug, PartialEq, Readable, Serialize)]
+struct Tuple(u16, Named);
+
+#[derive(Debug, PartialEq, Readable, Serialize)]
+struct Generic<T> {
+    first: T,
+    second: T,
+}
+
+#[derive(Debug, PartialEq, Readable, Serialize)]
+struct Unit;
+
+fn roundtrip<T>(value: &T) -> Vec<u8>
+where
+    T: Readable + Serialize<Error = std::io::Error> + PartialEq + std::fmt::Debug,
+{
+    let bytes = value.serialize_as_vec().unwrap();
+    let mut buf = ReadBuf::from(&bytes);
+    assert_eq!(&T::read(&mut buf).unwrap(), value);
+    buf.expect_end().unwrap();
+    bytes
+}
+
+#[test]
+fn fields_in_declaration_order() {
+    let named = Named {
+        tag: 1,
+        value: 0x0203,
+        hash: [4, 5, 6, 7],
+    };
+    assert_eq!(
+        roundtrip(&named),
+        vec![1, 0, 0, 0, 0, 0, 0, 2, 3, 4, 5, 6, 7]
+    );
+    assert_eq!(
+        roundtrip(&Tuple(0xabcd, named.clone()))[..3],
+        [0xab, 0xcd, 1]
+    );
+    assert_eq!(
+        roundtrip(&Generic {
+            first: 1u32,
+            second: 2u32
+        }),
+        vec![0, 0, 0, 1, 0, 0, 0, 2]
+    );
+    assert_eq!(roundtrip(&Unit), Vec::<u8>::new());
+}
+
+#[test]
+fn read_errors() {
+    let mut buf = ReadBuf::from(&[1, 2, 3]);
+    assert_eq!(
+        Named::read(&mut buf).unwrap_err(),
+        ReadError::NotEnoughBytes(2, 8)
+    );
+}
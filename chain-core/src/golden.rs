@@ -0,0 +1,221 @@
+//! Golden tests of the wire format.
+//!
+//! A fixture file records the expected serialization of a list of named
+//! values, one per line, as `<name> <hex bytes>`. Empty lines and lines
+//! starting with `#` are ignored. `check` fails if the serialization of any
+//! value differs from its fixture, if a fixture does not read back into its
+//! value, or if a fixture is not used anymore: any change in the bytes
+//! output for an existing value is caught, whatever the refactoring.
+//!
+//! `check` reads the fixtures back with `mempack::Readable`,
+//! `check_deserialize` with `property::Deserialize`, and `check_serialize`
+//! only compares the bytes, for the types which cannot be read back.
+//!
+//! When a change of the format is intended, run the tests with the
+//! environment variable `GOLDEN_UPDATE` set to rewrite the fixtures, and
+//! review the diff of the fixture files.
+
+use crate::mempack::{ReadBuf, Readable};
+use crate::property::{Deserialize, Serialize};
+use std::{fmt::Debug, fs, path::Path};
+
+/// environment variable requesting the fixtures to be rewritten
+pub const UPDATE_VAR: &str = "GOLDEN_UPDATE";
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// parse the content of a fixture file into its named entries, in order
+pub fn parse_fixtures(content: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap();
+        let hex = words.next().unwrap_or("");
+        let bytes = match (from_hex(hex), words.next()) {
+            (Some(bytes), None) => bytes,
+            _ => return Err(format!("line {}: expecting `<name> <hex>`", number + 1)),
+        };
+        if entries.iter().any(|(n, _)| n == name) {
+            return Err(format!("line {}: duplicate entry `{}`", number + 1, name));
+        }
+        entries.push((name.to_string(), bytes));
+    }
+    Ok(entries)
+}
+
+/// check the serialization of the named values against the fixture file,
+/// reading them back with `Readable`
+///
+/// See the module documentation.
+///
+/// # Panics
+///
+/// if the check fails: this is meant to be called from tests
+pub fn check<T, P>(path: P, cases: &[(&str, T)])
+where
+    T: Serialize + Readable + PartialEq + Debug,
+    T::Error: Debug,
+    P: AsRef<Path>,
+{
+    check_with(path.as_ref(), cases, |bytes, value| {
+        let mut buf = ReadBuf::from(bytes);
+        let decoded = T::read(&mut buf)
+            .and_then(|decoded| buf.expect_end().map(|()| decoded))
+            .map_err(|e| format!("cannot read: {}", e))?;
+        same_value(decoded, value)
+    })
+}
+
+/// same as `check`, reading the values back with `Deserialize`; all the
+/// bytes of a fixture have to be consumed
+///
+/// # Panics
+///
+/// if the check fails: this is meant to be called from tests
+pub fn check_deserialize<T, P>(path: P, cases: &[(&str, T)])
+where
+    T: Serialize + Deserialize + PartialEq + Debug,
+    <T as Serialize>::Error: Debug,
+    <T as Deserialize>::Error: Debug,
+    P: AsRef<Path>,
+{
+    check_with(path.as_ref(), cases, |bytes, value| {
+        let mut reader = bytes;
+        let decoded = T::deserialize(&mut reader).map_err(|e| format!("cannot read: {:?}", e))?;
+        if !reader.is_empty() {
+            return Err(format!("{} bytes left unread", reader.len()));
+        }
+        same_value(decoded, value)
+    })
+}
+
+/// same as `check` without reading the values back
+///
+/// # Panics
+///
+/// if the check fails: this is meant to be called from tests
+pub fn check_serialize<T, P>(path: P, cases: &[(&str, T)])
+where
+    T: Serialize,
+    T::Error: Debug,
+    P: AsRef<Path>,
+{
+    check_with(path.as_ref(), cases, |_, _| Ok(()))
+}
+
+fn same_value<T: PartialEq + Debug>(decoded: T, value: &T) -> Result<(), String> {
+    if &decoded == value {
+        Ok(())
+    } else {
+        Err(format!("reads back as {:?}", decoded))
+    }
+}
+
+/// `read_back` checks that the fixture bytes read back into the value
+fn check_with<T, F>(path: &Path, cases: &[(&str, T)], read_back: F)
+where
+    T: Serialize,
+    T::Error: Debug,
+    F: Fn(&[u8], &T) -> Result<(), String>,
+{
+    let serialized: Vec<(&str, Vec<u8>)> = cases
+        .iter()
+        .map(|(name, value)| (*name, value.serialize_as_vec().unwrap()))
+        .collect();
+
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        let mut content = format!(
+            "# generated by chain_core::golden, set {} to regenerate\n",
+            UPDATE_VAR
+        );
+        for (name, bytes) in &serialized {
+            content.push_str(&format!("{} {}\n", name, to_hex(bytes)));
+        }
+        fs::write(path, content)
+            .unwrap_or_else(|e| panic!("cannot write {}: {}", path.display(), e));
+        return;
+    }
+
+    let content = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "cannot read {}: {} (set {} to create it)",
+            path.display(),
+            e,
+            UPDATE_VAR
+        )
+    });
+    let fixtures = parse_fixtures(&content).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    for ((name, value), (_, bytes)) in cases.iter().zip(&serialized) {
+        let expected = match fixtures.iter().find(|(n, _)| n == name) {
+            Some((_, expected)) => expected,
+            None => panic!("{}: no fixture for `{}`", path.display(), name),
+        };
+        assert!(
+            bytes == expected,
+            "{}: serialization of `{}` changed\n  expected: {}\n  got:      {}",
+            path.display(),
+            name,
+            to_hex(expected),
+            to_hex(bytes)
+        );
+        read_back(expected, value)
+            .unwrap_or_else(|e| panic!("{}: `{}`: {}", path.display(), name, e));
+    }
+
+    for (name, _) in &fixtures {
+        assert!(
+            cases.iter().any(|(n, _)| n == name),
+            "{}: fixture `{}` is not checked anymore",
+            path.display(),
+            name
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0x00, 0x1f, 0xa0]), "001fa0");
+        assert_eq!(from_hex("001fA0"), Some(vec![0x00, 0x1f, 0xa0]));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("+f"), None);
+    }
+
+    #[test]
+    fn fixtures() {
+        let content = "# comment\n\nzero 00000000\nempty\n  one 00000001  \n";
+        assert_eq!(
+            parse_fixtures(content),
+            Ok(vec![
+                ("zero".to_string(), vec![0; 4]),
+                ("empty".to_string(), vec![]),
+                ("one".to_string(), vec![0, 0, 0, 1]),
+            ])
+        );
+        assert!(parse_fixtures("a 00\na 01").is_err());
+        assert!(parse_fixtures("a 00 01").is_err());
+        assert!(parse_fixtures("a 0").is_err());
+    }
+}
@@ -1,5 +1,8 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
 #[macro_use]
 extern crate cfg_if;
+extern crate alloc;
 
 cfg_if! {
     if #[cfg(test)] {
@@ -9,6 +12,16 @@ cfg_if! {
     }
 }
 
+#[cfg(any(all(test, feature = "std"), feature = "property-test-api"))]
+pub mod golden;
+#[cfg(feature = "std")]
+pub mod lightclient;
 pub mod mempack;
+#[cfg(feature = "std")]
 pub mod packer;
+#[cfg(feature = "std")]
 pub mod property;
+#[cfg(feature = "std")]
+pub mod store;
+#[cfg(any(all(test, feature = "std"), feature = "property-test-api"))]
+pub mod test_chain;
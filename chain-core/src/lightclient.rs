@@ -0,0 +1,272 @@
+//! Header-only chain verification
+//!
+//! A light client does not download block contents: it follows the chain
+//! by checking each header against the previously accepted one. This
+//! module verifies:
+//!
+//! * parent linkage: the header's parent is the current tip;
+//! * date monotonicity: the header's date is strictly after the tip's;
+//! * chain length: the header is exactly one block after the tip;
+//! * leadership: the header was produced by a legitimate leader, as
+//!   decided by a chain specific `LeaderVerifier`.
+//!
+//! Every accepted header adds its weight (as reported by the leader
+//! verifier, e.g. the stake of the leader or `1` for BFT) to the
+//! cumulative weight of the chain, which can be used to compare
+//! competing chains.
+
+use crate::property::{ChainLength as _, Header};
+use std::{error, fmt};
+
+/// Chain specific verification of the block producer of a header.
+pub trait LeaderVerifier<H: Header> {
+    type Error: error::Error;
+
+    /// verify the header has been signed by the expected leader and
+    /// return the weight this header contributes to the chain.
+    fn verify(&self, header: &H) -> Result<u64, Self::Error>;
+}
+
+/// Error returned when a header cannot extend the verified chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error<Id, E> {
+    /// the header's parent is not the current tip
+    ParentMismatch { expected: Id, got: Id },
+    /// the header's date is not after the current tip's date
+    DateNotIncreasing,
+    /// the header's chain length does not follow the tip's
+    InvalidChainLength,
+    /// the leader verifier rejected the header
+    Leader(E),
+}
+
+impl<Id: fmt::Debug, E: fmt::Display> fmt::Display for Error<Id, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ParentMismatch { expected, got } => write!(
+                f,
+                "header parent {:?} does not match the tip {:?}",
+                got, expected
+            ),
+            Error::DateNotIncreasing => write!(f, "header date is not after the tip's date"),
+            Error::InvalidChainLength => write!(f, "header chain length does not follow the tip"),
+            Error::Leader(e) => write!(f, "invalid leader: {}", e),
+        }
+    }
+}
+
+impl<Id: fmt::Debug, E: error::Error + 'static> error::Error for Error<Id, E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Leader(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The tip of a header chain verified by a light client.
+pub struct HeaderChain<H: Header, V> {
+    verifier: V,
+    tip_id: H::Id,
+    tip_date: H::Date,
+    tip_chain_length: H::ChainLength,
+    weight: u64,
+}
+
+impl<H, V> HeaderChain<H, V>
+where
+    H: Header,
+    V: LeaderVerifier<H>,
+{
+    /// start following the chain from the given trusted header (e.g. the
+    /// genesis header or a checkpoint).
+    pub fn new(anchor: &H, verifier: V) -> Self {
+        HeaderChain {
+            verifier,
+            tip_id: anchor.id(),
+            tip_date: anchor.date(),
+            tip_chain_length: anchor.chain_length(),
+            weight: 0,
+        }
+    }
+
+    /// identifier of the last accepted header
+    pub fn tip_id(&self) -> &H::Id {
+        &self.tip_id
+    }
+
+    /// date of the last accepted header
+    pub fn tip_date(&self) -> &H::Date {
+        &self.tip_date
+    }
+
+    /// chain length of the last accepted header
+    pub fn tip_chain_length(&self) -> &H::ChainLength {
+        &self.tip_chain_length
+    }
+
+    /// sum of the weights of all headers accepted since the anchor
+    pub fn cumulative_weight(&self) -> u64 {
+        self.weight
+    }
+
+    /// check the given header can extend the chain, without accepting it.
+    ///
+    /// On success return the weight the header would add to the chain.
+    pub fn verify(&self, header: &H) -> Result<u64, Error<H::Id, V::Error>> {
+        let parent = header.parent_id();
+        if parent != self.tip_id {
+            return Err(Error::ParentMismatch {
+                expected: self.tip_id.clone(),
+                got: parent,
+            });
+        }
+        if header.date() <= self.tip_date {
+            return Err(Error::DateNotIncreasing);
+        }
+        if header.chain_length() != self.tip_chain_length.next() {
+            return Err(Error::InvalidChainLength);
+        }
+        self.verifier.verify(header).map_err(Error::Leader)
+    }
+
+    /// verify the header and make it the new tip of the chain
+    pub fn push(&mut self, header: &H) -> Result<(), Error<H::Id, V::Error>> {
+        let weight = self.verify(header)?;
+        self.tip_id = header.id();
+        self.tip_date = header.date();
+        self.tip_chain_length = header.chain_length();
+        self.weight = self.weight.saturating_add(weight);
+        Ok(())
+    }
+
+    /// push the given headers in order, stopping at the first invalid one.
+    ///
+    /// The headers accepted before the failure remain part of the chain.
+    pub fn push_all<'a, I>(&mut self, headers: I) -> Result<(), Error<H::Id, V::Error>>
+    where
+        I: IntoIterator<Item = &'a H>,
+        H: 'a,
+    {
+        for header in headers {
+            self.push(header)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::Serialize;
+    use crate::test_chain::{Date, Id};
+    use std::io::{self, Write};
+
+    #[derive(Debug, Clone)]
+    struct TestHeader {
+        id: u32,
+        parent: u32,
+        date: u32,
+        length: u32,
+        leader: u32,
+    }
+
+    impl Serialize for TestHeader {
+        type Error = io::Error;
+        fn serialize<W: Write>(&self, mut writer: W) -> Result<(), io::Error> {
+            for v in &[self.id, self.parent, self.date, self.length, self.leader] {
+                writer.write_all(&v.to_be_bytes())?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Header for TestHeader {
+        type Id = Id;
+        type Date = Date;
+        type ChainLength = Date;
+        type Version = ();
+
+        fn id(&self) -> Id {
+            Id(self.id)
+        }
+        fn parent_id(&self) -> Id {
+            Id(self.parent)
+        }
+        fn date(&self) -> Date {
+            Date(self.date)
+        }
+        fn version(&self) {}
+        fn chain_length(&self) -> Date {
+            Date(self.length)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct UnknownLeader(u32);
+    impl fmt::Display for UnknownLeader {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "unknown leader {}", self.0)
+        }
+    }
+    impl error::Error for UnknownLeader {}
+
+    /// leaders are identified by their weight, 0 is not a leader
+    struct Leaders;
+    impl LeaderVerifier<TestHeader> for Leaders {
+        type Error = UnknownLeader;
+        fn verify(&self, header: &TestHeader) -> Result<u64, UnknownLeader> {
+            match header.leader {
+                0 => Err(UnknownLeader(0)),
+                w => Ok(w as u64),
+            }
+        }
+    }
+
+    fn header(id: u32, parent: u32, date: u32, length: u32, leader: u32) -> TestHeader {
+        TestHeader {
+            id,
+            parent,
+            date,
+            length,
+            leader,
+        }
+    }
+
+    #[test]
+    fn follow_valid_chain() {
+        let mut chain = HeaderChain::new(&header(1, 0, 0, 0, 1), Leaders);
+        let headers = vec![header(2, 1, 1, 1, 3), header(3, 2, 5, 2, 4)];
+        chain.push_all(&headers).unwrap();
+        assert_eq!(chain.tip_id(), &Id(3));
+        assert_eq!(chain.tip_date(), &Date(5));
+        assert_eq!(chain.tip_chain_length(), &Date(2));
+        assert_eq!(chain.cumulative_weight(), 7);
+    }
+
+    #[test]
+    fn reject_invalid_headers() {
+        let mut chain = HeaderChain::new(&header(1, 0, 10, 0, 1), Leaders);
+        assert_eq!(
+            chain.push(&header(2, 9, 11, 1, 1)),
+            Err(Error::ParentMismatch {
+                expected: Id(1),
+                got: Id(9)
+            })
+        );
+        assert_eq!(
+            chain.push(&header(2, 1, 10, 1, 1)),
+            Err(Error::DateNotIncreasing)
+        );
+        assert_eq!(
+            chain.push(&header(2, 1, 11, 2, 1)),
+            Err(Error::InvalidChainLength)
+        );
+        assert_eq!(
+            chain.push(&header(2, 1, 11, 1, 0)),
+            Err(Error::Leader(UnknownLeader(0)))
+        );
+        assert_eq!(chain.tip_id(), &Id(1));
+        assert_eq!(chain.cumulative_weight(), 0);
+    }
+}
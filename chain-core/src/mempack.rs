@@ -1,5 +1,15 @@
-use std::error::Error;
-use std::fmt;
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{
+    error::Error,
+    io::{self, Read},
+};
 
 /// A local memory buffer to serialize data to
 pub struct WriteBuf(Vec<u8>);
@@ -41,6 +51,42 @@ pub enum ReadError {
     StructureInvalid(String),
     /// Unknown enumeration tag
     UnknownTag(u32),
+    /// An error raised while reading a part of the data, see
+    /// `ReadBuf::with_context`
+    WithContext {
+        /// absolute byte offset in the top level buffer where reading failed
+        offset: usize,
+        /// names of the enclosing structures, outermost first
+        path: Vec<String>,
+        error: Box<ReadError>,
+    },
+}
+
+impl ReadError {
+    /// the underlying error, stripped of its context
+    pub fn root(&self) -> &ReadError {
+        match self {
+            ReadError::WithContext { error, .. } => error.root(),
+            e => e,
+        }
+    }
+
+    /// absolute byte offset where reading failed, if known
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ReadError::WithContext { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// names of the structures being read when the error occurred,
+    /// outermost first
+    pub fn path(&self) -> &[String] {
+        match self {
+            ReadError::WithContext { path, .. } => path,
+            _ => &[],
+        }
+    }
 }
 
 impl fmt::Display for ReadError {
@@ -59,15 +105,31 @@ impl fmt::Display for ReadError {
             ),
             ReadError::StructureInvalid(s) => write!(f, "Structure invalid: {}", s),
             ReadError::UnknownTag(t) => write!(f, "Unknown tag: {}", t),
+            ReadError::WithContext {
+                offset,
+                path,
+                error,
+            } => {
+                write!(f, "{} at byte {}", error, offset)?;
+                if !path.is_empty() {
+                    write!(f, " in {}", path.join(" > "))?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for ReadError {}
 
 /// A local memory slice to read from memory
 pub struct ReadBuf<'a> {
     offset: usize,
+    /// absolute offset of `data` in the top level buffer
+    base: usize,
+    /// whether this is a sub-buffer returned by `split_to`
+    split: bool,
     data: &'a [u8],
 }
 
@@ -76,20 +138,108 @@ impl<'a> ReadBuf<'a> {
     pub fn from(slice: &'a [u8]) -> Self {
         ReadBuf {
             offset: 0,
+            base: 0,
+            split: false,
             data: slice,
         }
     }
 
+    /// Return the absolute position of the next byte to read, counted from
+    /// the start of the top level buffer this one was split from
+    pub fn position(&self) -> usize {
+        self.base + self.offset
+    }
+
+    /// Read a part of the data, annotating the errors with `context`
+    ///
+    /// Errors returned by `f` are wrapped in `ReadError::WithContext`,
+    /// recording the position in the buffer where reading failed, so the
+    /// context of data read from a sub-buffer (see `split_to`) should be
+    /// set on the sub-buffer. Nested calls accumulate the path to the
+    /// failing structure:
+    ///
+    /// ```
+    /// use chain_core::mempack::{ReadBuf, ReadError};
+    ///
+    /// let mut buf = ReadBuf::from(&[0, 1, 2]);
+    /// let err = buf
+    ///     .with_context("block", |buf| {
+    ///         buf.get_u16()?;
+    ///         buf.with_context("transaction", |buf| buf.get_u32())
+    ///     })
+    ///     .unwrap_err();
+    /// assert_eq!(err.root(), &ReadError::NotEnoughBytes(1, 4));
+    /// assert_eq!(err.offset(), Some(2));
+    /// assert_eq!(err.path(), &["block", "transaction"]);
+    /// ```
+    pub fn with_context<C, T, F>(&mut self, context: C, f: F) -> Result<T, ReadError>
+    where
+        C: fmt::Display,
+        F: FnOnce(&mut Self) -> Result<T, ReadError>,
+    {
+        f(self).map_err(|error| match error {
+            ReadError::WithContext {
+                offset,
+                mut path,
+                error,
+            } => {
+                path.insert(0, context.to_string());
+                ReadError::WithContext {
+                    offset,
+                    path,
+                    error,
+                }
+            }
+            error => ReadError::WithContext {
+                offset: self.position(),
+                path: vec![context.to_string()],
+                error: Box::new(error),
+            },
+        })
+    }
+
     fn left(&self) -> usize {
         self.data.len() - self.offset
     }
 
+    /// Return the number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.left()
+    }
+
+    /// Check that `count` elements of at least `min_item_size` bytes each
+    /// can fit in the rest of the buffer
+    ///
+    /// To call on the lengths read from the data before allocating
+    /// anything, so a malicious payload cannot claim a huge number of
+    /// elements. Return the count, or `ReadError::SizeTooBig` with the
+    /// maximum count the buffer could hold.
+    pub fn check_count(&self, count: usize, min_item_size: usize) -> Result<usize, ReadError> {
+        if min_item_size == 0 {
+            return Ok(count);
+        }
+        let max = self.left() / min_item_size;
+        if count <= max {
+            Ok(count)
+        } else {
+            Err(ReadError::SizeTooBig(count, max))
+        }
+    }
+
+    /// In a sub-buffer, the error records the position where it ran out
+    /// of bytes, so it can be told apart from the end of the top level
+    /// buffer (see `StreamReader::read_next`)
     fn assure_size(&self, expected: usize) -> Result<(), ReadError> {
         let left = self.left();
         if left >= expected {
             Ok(())
+        } else if self.split {
+            Err(ReadError::WithContext {
+                offset: self.position(),
+                path: Vec::new(),
+                error: Box::new(ReadError::NotEnoughBytes(left, expected)),
+            })
         } else {
-            dbg!(self.data);
             Err(ReadError::NotEnoughBytes(left, expected))
         }
     }
@@ -126,8 +276,14 @@ impl<'a> ReadBuf<'a> {
 
     /// Return a sub-buffer ending at the given byte offset
     pub fn split_to(&mut self, sz: usize) -> Result<ReadBuf<'a>, ReadError> {
+        let base = self.position();
         let slice = self.get_slice(sz)?;
-        Ok(ReadBuf::from(slice))
+        Ok(ReadBuf {
+            offset: 0,
+            base,
+            split: true,
+            data: slice,
+        })
     }
 
     /// Return the next u8 from the buffer
@@ -209,9 +365,19 @@ read_array_impls! {
     4 8 12 16 20 24 28 32 64 96 128
 }
 
+/// number of `T` to allocate upfront for reading `n` of them, so that no
+/// more bytes than the `left` bytes of input are allocated
+fn vec_capacity<T>(n: usize, left: usize) -> usize {
+    core::cmp::min(n, left / core::cmp::max(1, core::mem::size_of::<T>()))
+}
+
 /// read N times for a T elements in sequences
+///
+/// The memory allocated upfront is at most the number of bytes left in the
+/// buffer, whatever `n` and the in-memory size of `T`, see also
+/// `ReadBuf::check_count`.
 pub fn read_vec<'a, T: Readable>(readbuf: &mut ReadBuf<'a>, n: usize) -> Result<Vec<T>, ReadError> {
-    let mut v = Vec::with_capacity(n);
+    let mut v = Vec::with_capacity(vec_capacity::<T>(n, readbuf.left()));
     for _ in 0..n {
         let t = T::read(readbuf)?;
         v.push(t)
@@ -232,6 +398,7 @@ pub fn read_mut_slice<'a, T: Readable>(
 }
 
 /// Transform a raw buffer into a Header
+#[cfg(feature = "std")]
 pub fn read_from_raw<T: Readable>(raw: &[u8]) -> Result<T, std::io::Error> {
     let mut rbuf = ReadBuf::from(raw);
     match T::read(&mut rbuf) {
@@ -252,3 +419,336 @@ pub fn read_from_raw<T: Readable>(raw: &[u8]) -> Result<T, std::io::Error> {
         },
     }
 }
+
+/// Default number of bytes read at once from the underlying stream
+#[cfg(feature = "std")]
+const STREAM_READ_CHUNK: usize = 4096;
+
+/// Read `Readable` items one after the other from a `std::io::Read`
+///
+/// Only the bytes of the item being parsed (plus at most one read chunk)
+/// are kept in memory, the internal buffer never grows above the limit
+/// given at construction: an item requiring more bytes than this limit
+/// fails with `ReadError::SizeTooBig`.
+#[cfg(feature = "std")]
+pub struct StreamReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    position: usize,
+    max_buffer_size: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> StreamReader<R> {
+    /// Create a stream reader that will never buffer more than
+    /// `max_buffer_size` bytes
+    ///
+    /// # Panics
+    ///
+    /// if `max_buffer_size` is 0
+    pub fn new(reader: R, max_buffer_size: usize) -> Self {
+        assert!(max_buffer_size > 0, "the buffer cannot be empty");
+        StreamReader {
+            reader,
+            buffer: Vec::new(),
+            position: 0,
+            max_buffer_size,
+        }
+    }
+
+    /// Return the underlying reader and the bytes buffered but not consumed yet
+    pub fn into_inner(mut self) -> (R, Vec<u8>) {
+        self.buffer.drain(..self.position);
+        (self.reader, self.buffer)
+    }
+
+    fn buffered(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    /// read at least `needed` more bytes, unless the end of the stream is
+    /// reached. Return the number of bytes read.
+    ///
+    /// The reader is not called again once `needed` bytes are buffered, so
+    /// this never waits for more data than the item being parsed requires.
+    fn fill(&mut self, needed: usize) -> io::Result<usize> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        let start = self.buffer.len();
+        let room = self.max_buffer_size - start;
+        let want = std::cmp::min(std::cmp::max(needed, STREAM_READ_CHUNK), room);
+        self.buffer.resize(start + want, 0);
+        let mut total = 0;
+        let result = loop {
+            if total >= needed {
+                break Ok(total);
+            }
+            match self.reader.read(&mut self.buffer[start + total..]) {
+                Ok(0) => break Ok(total),
+                Ok(n) => total += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.buffer.truncate(start + total);
+        result
+    }
+
+    /// Read the next item from the stream
+    ///
+    /// Return `None` if the stream ended cleanly between two items, and an
+    /// `UnexpectedEof` error if it ended in the middle of an item.
+    ///
+    /// The parsing of an item restarts from its first byte every time more
+    /// data has to be read, and the reader is not called again once the
+    /// bytes demanded by the failing field have arrived. An item of `n`
+    /// bytes may then be parsed once per field when the stream delivers it
+    /// in small reads, up to `n` times: `O(n²)` work at worst. This is
+    /// meant for small items, such as network messages.
+    pub fn read_next<T: Readable>(&mut self) -> io::Result<Option<T>> {
+        if self.buffered() == 0 && self.fill(1)? == 0 {
+            return Ok(None);
+        }
+        loop {
+            let mut buf = ReadBuf::from(&self.buffer[self.position..]);
+            let missing = match T::read(&mut buf) {
+                Ok(t) => {
+                    self.position += buf.offset;
+                    return Ok(Some(t));
+                }
+                // more data only helps if the end of the buffered bytes was
+                // reached, not the end of a sub-buffer
+                Err(e) => match (e.root(), e.offset()) {
+                    (ReadError::NotEnoughBytes(left, demanded), offset)
+                        if offset.is_none_or(|o| o + left == self.buffered()) =>
+                    {
+                        std::cmp::max(demanded.saturating_sub(*left), 1)
+                    }
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                },
+            };
+            let needed = self.buffered() + missing;
+            if needed > self.max_buffer_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    ReadError::SizeTooBig(needed, self.max_buffer_size),
+                ));
+            }
+            if self.fill(missing)? < missing {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    pub(super) fn encode(payloads: &[&[u8]]) -> Vec<u8> {
+        let mut buf = WriteBuf::new();
+        for p in payloads {
+            buf.put_u16(p.len() as u16);
+            buf.put_bytes(p);
+        }
+        buf.0
+    }
+
+    #[test]
+    fn error_context() {
+        let data = encode(&[b"abc", &[1; 10]]);
+        let mut buf = ReadBuf::from(&data);
+        let err = buf
+            .with_context("block", |buf| {
+                let len = buf.get_u16()? as usize;
+                buf.skip_bytes(len)?;
+                let mut sub = buf.split_to(4)?;
+                for i in 0.. {
+                    sub.with_context(format!("message[{}]", i), |sub| sub.get_u16())?;
+                }
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.root(), &ReadError::NotEnoughBytes(0, 2));
+        assert_eq!(err.offset(), Some(9));
+        assert_eq!(err.path(), &["block".to_string(), "message[2]".to_string()]);
+        assert_eq!(
+            err.to_string(),
+            "NotEnoughBytes: demanded 2 bytes but got 0 at byte 9 in block > message[2]"
+        );
+
+        let err = ReadError::UnconsumedData(1);
+        assert_eq!(err.root(), &err);
+        assert_eq!(err.offset(), None);
+        assert!(err.path().is_empty());
+    }
+
+    #[test]
+    fn bounded_counts() {
+        let data = [0; 10];
+        let buf = ReadBuf::from(&data);
+        assert_eq!(buf.remaining(), 10);
+        assert_eq!(buf.check_count(5, 2), Ok(5));
+        assert_eq!(
+            buf.check_count(65535, 2),
+            Err(ReadError::SizeTooBig(65535, 5))
+        );
+        assert_eq!(buf.check_count(65535, 0), Ok(65535));
+
+        let mut buf = ReadBuf::from(&data);
+        assert_eq!(
+            read_vec::<u32>(&mut buf, usize::MAX),
+            Err(ReadError::NotEnoughBytes(2, 4))
+        );
+    }
+
+    #[test]
+    fn bounded_allocation() {
+        assert_eq!(vec_capacity::<u8>(usize::MAX, 1000), 1000);
+        assert_eq!(vec_capacity::<u32>(usize::MAX, 1000), 250);
+        assert_eq!(vec_capacity::<[u8; 128]>(usize::MAX, 1000), 7);
+        assert_eq!(vec_capacity::<[u8; 128]>(3, 1000), 3);
+        assert_eq!(vec_capacity::<()>(usize::MAX, 1000), 1000);
+
+        let data = [7; 300];
+        let mut buf = ReadBuf::from(&data);
+        let v = read_vec::<[u8; 128]>(&mut buf, 2).unwrap();
+        assert_eq!(v, vec![[7; 128]; 2]);
+        assert!(v.capacity() * 128 <= data.len());
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod stream_tests {
+    use super::tests::encode;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Payload(Vec<u8>);
+
+    impl Readable for Payload {
+        fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+            let len = buf.get_u16()? as usize;
+            Ok(Payload(buf.get_slice(len)?.to_vec()))
+        }
+    }
+
+    /// a reader returning at most one byte per call
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    /// a reader failing if read again once its data is exhausted, like a
+    /// socket blocking until the peer sends more
+    struct NoMoreData<'a>(&'a [u8]);
+
+    impl<'a> Read for NoMoreData<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = std::cmp::min(buf.len(), self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn stream_read_items() {
+        let big = vec![0xaa; 3 * STREAM_READ_CHUNK];
+        let data = encode(&[b"hello", &[], &big, b"world"]);
+        for max in &[big.len() + 2, 8 * STREAM_READ_CHUNK] {
+            let mut stream = StreamReader::new(Trickle(&data), *max);
+            assert_eq!(
+                stream.read_next().unwrap(),
+                Some(Payload(b"hello".to_vec()))
+            );
+            assert_eq!(stream.read_next().unwrap(), Some(Payload(vec![])));
+            assert_eq!(stream.read_next().unwrap(), Some(Payload(big.clone())));
+            assert_eq!(
+                stream.read_next().unwrap(),
+                Some(Payload(b"world".to_vec()))
+            );
+            assert_eq!(stream.read_next::<Payload>().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn stream_read_available_items() {
+        let mut stream = StreamReader::new(NoMoreData(&[0, 0, 0, 7]), 1024);
+        assert_eq!(stream.read_next::<u32>().unwrap(), Some(7));
+
+        let data = encode(&[b"hello", b"world"]);
+        let mut stream = StreamReader::new(NoMoreData(&data), 1024);
+        assert_eq!(
+            stream.read_next().unwrap(),
+            Some(Payload(b"hello".to_vec()))
+        );
+        assert_eq!(
+            stream.read_next().unwrap(),
+            Some(Payload(b"world".to_vec()))
+        );
+        assert!(stream.read_next::<Payload>().is_err());
+    }
+
+    #[test]
+    fn stream_read_errors() {
+        let data = encode(&[&[1; 100]]);
+
+        let mut stream = StreamReader::new(&data[..], 64);
+        let err = stream.read_next::<Payload>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut stream = StreamReader::new(&data[..50], 1024);
+        let err = stream.read_next::<Payload>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn stream_read_truncated_sub_buffer() {
+        #[derive(Debug, PartialEq)]
+        struct Framed(u32);
+
+        impl Readable for Framed {
+            fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+                let len = buf.get_u8()? as usize;
+                let mut sub = buf.split_to(len)?;
+                let v = sub.get_u32()?;
+                sub.expect_end()?;
+                Ok(Framed(v))
+            }
+        }
+
+        // the declared length of the first item is too short for its body,
+        // the bytes of the second item must not be read to complete it
+        let data = [2, 0, 0, 4, 0, 0, 0, 7];
+        let mut stream = StreamReader::new(NoMoreData(&data), 1024);
+        let err = stream.read_next::<Framed>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // the sub-buffer first ends with the buffered bytes
+        let mut stream = StreamReader::new(Trickle(&data), 1024);
+        let err = stream.read_next::<Framed>().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut stream = StreamReader::new(Trickle(&data[3..]), 1024);
+        assert_eq!(stream.read_next().unwrap(), Some(Framed(7)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn stream_reader_empty_buffer() {
+        StreamReader::new(&[0u8][..], 0);
+    }
+}
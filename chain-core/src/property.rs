@@ -329,6 +329,41 @@ where
     }
 }
 
+macro_rules! serialize_prim_impl {
+    ($($Ty: ty)+) => {
+        $(
+        impl Serialize for $Ty {
+            type Error = std::io::Error;
+
+            fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+                writer.write_all(&self.to_be_bytes())
+            }
+        }
+        )+
+    };
+}
+
+// big endian, as read by the `Readable` implementations of the mempack
+serialize_prim_impl! { u8 u16 u32 u64 u128 }
+
+macro_rules! serialize_array_impls {
+    ($($N: expr)+) => {
+        $(
+        impl Serialize for [u8; $N] {
+            type Error = std::io::Error;
+
+            fn serialize<W: std::io::Write>(&self, mut writer: W) -> Result<(), Self::Error> {
+                writer.write_all(self)
+            }
+        }
+        )+
+    };
+}
+
+serialize_array_impls! {
+    4 8 12 16 20 24 28 32 64 96 128
+}
+
 impl<T: Serialize> Serialize for &T {
     type Error = T::Error;
 
@@ -0,0 +1,372 @@
+//! Block storage interface
+//!
+//! The `BlockStore` trait defines the operations a node needs from its
+//! persistent block storage. Backends only implement the primitive
+//! accessors; the policy (parent checks, depth computation, range
+//! iteration) is provided by the trait so all backends behave the same.
+
+pub mod memory;
+
+use crate::property::{Block, BlockId, HasHeader};
+use std::{collections::HashSet, error, fmt, vec};
+
+/// Errors returned by the block store operations
+#[derive(Debug)]
+pub enum Error {
+    /// the requested block is not in the store
+    BlockNotFound,
+    /// the parent of the block to store is not in the store
+    MissingParent,
+    /// the start of a range is not an ancestor of its end
+    CannotIterate,
+    /// error from the storage backend
+    BackendError(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BlockNotFound => write!(f, "block not found"),
+            Error::MissingParent => write!(f, "the parent block is missing for the required write"),
+            Error::CannotIterate => write!(f, "cannot iterate between the 2 given blocks"),
+            Error::BackendError(_) => write!(f, "miscellaneous storage error"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::BackendError(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Information the store keeps about every block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo<Id: BlockId> {
+    pub block_hash: Id,
+
+    /// identifier of the parent block, `Id::zero()` for the genesis block
+    pub parent_id: Id,
+
+    /// number of blocks between this block and the genesis block
+    /// (the genesis block has depth 0)
+    pub depth: u64,
+
+    /// identifier of the ancestor at depth `fast_depth(depth)`, used to
+    /// skip over many blocks at once when looking for an ancestor.
+    /// `Id::zero()` for the genesis block.
+    pub fast_ancestor: Id,
+}
+
+/// depth of the block pointed to by `BlockInfo::fast_ancestor`
+///
+/// This clears the lowest set bit of the depth, so any ancestor can be
+/// reached in a logarithmic number of jumps.
+pub fn fast_depth(depth: u64) -> u64 {
+    depth & depth.saturating_sub(1)
+}
+
+/// tag of the stable tip of the last `prune_forks`, the forks below it
+/// are already removed
+pub const PRUNED_TAG: &str = "chain_core::pruned";
+
+pub type BlockStoreId<S> = <<S as BlockStore>::Block as Block>::Id;
+pub type BlockStoreInfo<S> = BlockInfo<BlockStoreId<S>>;
+pub type BlockStoreHeader<S> = <<S as BlockStore>::Block as HasHeader>::Header;
+
+pub trait BlockStore {
+    type Block: Block + HasHeader;
+
+    /// write a block and its information without any check
+    fn put_block_internal(
+        &mut self,
+        block: &Self::Block,
+        block_info: BlockStoreInfo<Self>,
+    ) -> Result<(), Error>;
+
+    /// get a block and its information
+    fn get_block(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<(Self::Block, BlockStoreInfo<Self>), Error>;
+
+    /// get the information of a block without loading the block itself
+    fn get_block_info(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<BlockStoreInfo<Self>, Error>;
+
+    /// get the identifiers of all the stored blocks at the given depth
+    fn block_ids_at_depth(&self, depth: u64) -> Result<Vec<BlockStoreId<Self>>, Error>;
+
+    /// remove a block and the tags pointing to it without any check
+    fn remove_block_internal(&mut self, block_hash: &BlockStoreId<Self>) -> Result<(), Error>;
+
+    /// reclaim the space freed by removed blocks, if the backend needs it
+    ///
+    /// This may rewrite the whole storage, so it is never called
+    /// implicitly.
+    fn compact(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// check if the block is present in the store
+    fn block_exists(&self, block_hash: &BlockStoreId<Self>) -> Result<bool, Error> {
+        match self.get_block_info(block_hash) {
+            Ok(_) => Ok(true),
+            Err(Error::BlockNotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// associate the given name to a block (e.g. `HEAD`)
+    fn put_tag(&mut self, tag_name: &str, block_hash: &BlockStoreId<Self>) -> Result<(), Error>;
+
+    /// get the block associated to the given name, if any
+    fn get_tag(&self, tag_name: &str) -> Result<Option<BlockStoreId<Self>>, Error>;
+
+    /// store a block whose parent is already in the store
+    ///
+    /// Storing a block that is already present does nothing. The genesis
+    /// block (whose parent is `Id::zero()`) can always be stored.
+    fn put_block(&mut self, block: &Self::Block) -> Result<(), Error> {
+        let block_hash = block.id();
+        if self.block_exists(&block_hash)? {
+            return Ok(());
+        }
+
+        let parent_id = block.parent_id();
+        let block_info = if parent_id == BlockId::zero() {
+            BlockInfo {
+                block_hash,
+                parent_id,
+                depth: 0,
+                fast_ancestor: BlockId::zero(),
+            }
+        } else {
+            let depth = match self.get_block_info(&parent_id) {
+                Ok(parent_info) => parent_info.depth + 1,
+                Err(Error::BlockNotFound) => return Err(Error::MissingParent),
+                Err(err) => return Err(err),
+            };
+            let fast_ancestor = self.block_at_depth(&parent_id, fast_depth(depth))?;
+            BlockInfo {
+                block_hash,
+                parent_id,
+                depth,
+                fast_ancestor: fast_ancestor.block_hash,
+            }
+        };
+        self.put_block_internal(block, block_info)
+    }
+
+    /// get the ancestor of the given block at the given depth (or the
+    /// block itself if it is at that depth)
+    ///
+    /// Return `BlockNotFound` if the depth is above the block's depth.
+    fn block_at_depth(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+        depth: u64,
+    ) -> Result<BlockStoreInfo<Self>, Error> {
+        let mut current = self.get_block_info(block_hash)?;
+        if depth > current.depth {
+            return Err(Error::BlockNotFound);
+        }
+        while current.depth > depth {
+            current = if fast_depth(current.depth) >= depth {
+                self.get_block_info(&current.fast_ancestor)?
+            } else {
+                self.get_block_info(&current.parent_id)?
+            };
+        }
+        Ok(current)
+    }
+
+    /// check whether `ancestor` is an ancestor of `descendant` (or the
+    /// same block)
+    fn is_ancestor(
+        &self,
+        ancestor: &BlockStoreId<Self>,
+        descendant: &BlockStoreId<Self>,
+    ) -> Result<bool, Error> {
+        let ancestor_info = self.get_block_info(ancestor)?;
+        match self.block_at_depth(descendant, ancestor_info.depth) {
+            Ok(info) => Ok(&info.block_hash == ancestor),
+            Err(Error::BlockNotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// get the last final block of the chain of `tip`: the ancestor
+    /// `stability_depth` blocks below `tip`, or the genesis block if the
+    /// chain is shorter than that
+    ///
+    /// The blocks up to the stable tip can no longer be rolled back.
+    fn stable_tip(
+        &self,
+        tip: &BlockStoreId<Self>,
+        stability_depth: u64,
+    ) -> Result<BlockStoreInfo<Self>, Error> {
+        let depth = self
+            .get_block_info(tip)?
+            .depth
+            .saturating_sub(stability_depth);
+        self.block_at_depth(tip, depth)
+    }
+
+    /// check whether the block is final in the chain of `tip`, i.e. it is
+    /// the stable tip or one of its ancestors
+    fn is_stable(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+        tip: &BlockStoreId<Self>,
+        stability_depth: u64,
+    ) -> Result<bool, Error> {
+        let stable_tip = self.stable_tip(tip, stability_depth)?;
+        self.is_ancestor(block_hash, &stable_tip.block_hash)
+    }
+
+    /// remove the forks that can no longer become the main chain. Return
+    /// the number of removed blocks.
+    ///
+    /// The chain of `tip` is final up to its stable tip (see
+    /// `stable_tip`): every block that diverged from it at or below the
+    /// stable tip is removed with all its descendants, including the
+    /// genesis blocks of other chains. Forks that diverged above the stable
+    /// tip are kept.
+    ///
+    /// The stable tip is remembered with the `PRUNED_TAG` tag, and the next
+    /// prune only looks at the blocks above it when it is still in the
+    /// chain of the new stable tip. Forks added below it in the meantime,
+    /// which a node should refuse anyway (see `is_stable`), are therefore
+    /// not removed. The space of the removed blocks is only reclaimed by
+    /// `compact`.
+    fn prune_forks(
+        &mut self,
+        tip: &BlockStoreId<Self>,
+        stability_depth: u64,
+    ) -> Result<usize, Error> {
+        let stable_tip = self.stable_tip(tip, stability_depth)?;
+        let stable_depth = stable_tip.depth;
+
+        let mut dead = Vec::new();
+        let mut dead_parents = HashSet::new();
+        let mut depth = first_unpruned_depth(self, &stable_tip.block_hash)?;
+        while depth <= stable_depth || !dead_parents.is_empty() {
+            let mut dead_at_depth = HashSet::new();
+            if depth <= stable_depth {
+                let main = self.block_at_depth(tip, depth)?.block_hash;
+                for block_hash in self.block_ids_at_depth(depth)? {
+                    if block_hash != main {
+                        dead_at_depth.insert(block_hash);
+                    }
+                }
+            } else {
+                for block_hash in self.block_ids_at_depth(depth)? {
+                    if dead_parents.contains(&self.get_block_info(&block_hash)?.parent_id) {
+                        dead_at_depth.insert(block_hash);
+                    }
+                }
+            }
+            dead.extend(dead_at_depth.iter().cloned());
+            dead_parents = dead_at_depth;
+            depth += 1;
+        }
+
+        for block_hash in dead.iter() {
+            self.remove_block_internal(block_hash)?;
+        }
+        self.put_tag(PRUNED_TAG, &stable_tip.block_hash)?;
+        Ok(dead.len())
+    }
+
+    /// get the header of a block
+    fn get_header(&self, block_hash: &BlockStoreId<Self>) -> Result<BlockStoreHeader<Self>, Error> {
+        self.get_block(block_hash).map(|(block, _)| block.header())
+    }
+
+    /// get the identifiers of the blocks from `from` (excluded) to `to`
+    /// (included) in chain order
+    ///
+    /// Return `CannotIterate` if `from` is not an ancestor of `to` (or
+    /// `to` itself, in which case the range is empty). The default
+    /// implementation follows the parent of every block of the range;
+    /// backends able to fetch the whole range at once should override it.
+    fn range_ids(
+        &self,
+        from: &BlockStoreId<Self>,
+        to: &BlockStoreId<Self>,
+    ) -> Result<Vec<BlockStoreId<Self>>, Error> {
+        let from_depth = self.get_block_info(from)?.depth;
+        let mut ids = Vec::new();
+        let mut current = self.get_block_info(to)?;
+        while current.depth > from_depth {
+            let parent_id = current.parent_id.clone();
+            ids.push(current.block_hash);
+            current = self.get_block_info(&parent_id)?;
+        }
+        if &current.block_hash != from {
+            return Err(Error::CannotIterate);
+        }
+        ids.reverse();
+        Ok(ids)
+    }
+
+    /// return the blocks from `from` (excluded) to `to` (included) in
+    /// chain order, see `range_ids`
+    ///
+    /// The blocks are loaded one at a time as the iterator advances.
+    fn iter_range(
+        &self,
+        from: &BlockStoreId<Self>,
+        to: &BlockStoreId<Self>,
+    ) -> Result<BlockIterator<'_, Self>, Error>
+    where
+        Self: Sized,
+    {
+        let ids = self.range_ids(from, to)?;
+        Ok(BlockIterator {
+            store: self,
+            ids: ids.into_iter(),
+        })
+    }
+}
+
+/// first depth at which `prune_forks` has to look for dead blocks, given
+/// the new stable tip: above the last pruned stable tip if it is an
+/// ancestor of the new one, from the genesis otherwise
+pub fn first_unpruned_depth<S: BlockStore + ?Sized>(
+    store: &S,
+    stable_tip: &BlockStoreId<S>,
+) -> Result<u64, Error> {
+    match store.get_tag(PRUNED_TAG)? {
+        Some(pruned) if store.is_ancestor(&pruned, stable_tip)? => {
+            Ok(store.get_block_info(&pruned)?.depth + 1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// Iterator over a range of blocks of a `BlockStore`, see `iter_range`
+pub struct BlockIterator<'a, S: BlockStore> {
+    store: &'a S,
+    ids: vec::IntoIter<BlockStoreId<S>>,
+}
+
+impl<'a, S: BlockStore> Iterator for BlockIterator<'a, S> {
+    type Item = Result<(S::Block, BlockStoreInfo<S>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next().map(|id| self.store.get_block(&id))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, S: BlockStore> ExactSizeIterator for BlockIterator<'a, S> {}
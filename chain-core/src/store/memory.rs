@@ -0,0 +1,207 @@
+//! In-memory implementation of the `BlockStore`
+//!
+//! Nothing is persisted: this is meant for tests and for nodes that do
+//! not need to keep the chain across restarts.
+
+use super::{BlockInfo, BlockStore, BlockStoreId, BlockStoreInfo, Error};
+use crate::property::{Block, HasHeader};
+use std::collections::HashMap;
+
+pub struct MemoryBlockStore<B: Block> {
+    blocks: HashMap<B::Id, (B, BlockInfo<B::Id>)>,
+    depths: HashMap<u64, Vec<B::Id>>,
+    tags: HashMap<String, B::Id>,
+}
+
+impl<B: Block> MemoryBlockStore<B> {
+    pub fn new() -> Self {
+        MemoryBlockStore {
+            blocks: HashMap::new(),
+            depths: HashMap::new(),
+            tags: HashMap::new(),
+        }
+    }
+}
+
+impl<B: Block> Default for MemoryBlockStore<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B> BlockStore for MemoryBlockStore<B>
+where
+    B: Block + HasHeader + Clone,
+{
+    type Block = B;
+
+    fn put_block_internal(
+        &mut self,
+        block: &B,
+        block_info: BlockStoreInfo<Self>,
+    ) -> Result<(), Error> {
+        self.depths
+            .entry(block_info.depth)
+            .or_default()
+            .push(block_info.block_hash.clone());
+        self.blocks
+            .insert(block_info.block_hash.clone(), (block.clone(), block_info));
+        Ok(())
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<(B, BlockStoreInfo<Self>), Error> {
+        self.blocks
+            .get(block_hash)
+            .cloned()
+            .ok_or(Error::BlockNotFound)
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<BlockStoreInfo<Self>, Error> {
+        self.blocks
+            .get(block_hash)
+            .map(|(_, info)| info.clone())
+            .ok_or(Error::BlockNotFound)
+    }
+
+    fn block_ids_at_depth(&self, depth: u64) -> Result<Vec<BlockStoreId<Self>>, Error> {
+        Ok(self.depths.get(&depth).cloned().unwrap_or_default())
+    }
+
+    fn remove_block_internal(&mut self, block_hash: &BlockStoreId<Self>) -> Result<(), Error> {
+        let (_, info) = self.blocks.remove(block_hash).ok_or(Error::BlockNotFound)?;
+        if let Some(ids) = self.depths.get_mut(&info.depth) {
+            ids.retain(|id| id != block_hash);
+        }
+        self.tags.retain(|_, id| id != block_hash);
+        Ok(())
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &BlockStoreId<Self>) -> Result<(), Error> {
+        if !self.blocks.contains_key(block_hash) {
+            return Err(Error::BlockNotFound);
+        }
+        self.tags.insert(tag_name.to_owned(), block_hash.clone());
+        Ok(())
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<BlockStoreId<Self>>, Error> {
+        Ok(self.tags.get(tag_name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_chain::{check_prune_forks, linear_chain, Id, TestBlock};
+
+    /// genesis 1, main chain 1 <- 2 <- 3 <- 4, fork 2 <- 5
+    fn populated_store() -> MemoryBlockStore<TestBlock> {
+        let mut store = MemoryBlockStore::new();
+        for block in &[
+            TestBlock(1, 0, 0),
+            TestBlock(2, 1, 1),
+            TestBlock(3, 2, 2),
+            TestBlock(4, 3, 3),
+            TestBlock(5, 2, 2),
+        ] {
+            store.put_block(block).unwrap();
+        }
+        store
+    }
+
+    fn range_ids(store: &MemoryBlockStore<TestBlock>, from: u32, to: u32) -> Vec<u32> {
+        store
+            .iter_range(&Id(from), &Id(to))
+            .unwrap()
+            .map(|r| r.unwrap().0 .0)
+            .collect()
+    }
+
+    #[test]
+    fn put_and_get() {
+        let mut store = populated_store();
+        let (block, info) = store.get_block(&Id(4)).unwrap();
+        assert_eq!(block, TestBlock(4, 3, 3));
+        assert_eq!(info.parent_id, Id(3));
+        assert_eq!(info.depth, 3);
+        assert_eq!(store.get_block_info(&Id(5)).unwrap().depth, 2);
+        assert_eq!(store.get_header(&Id(2)).unwrap(), TestBlock(2, 1, 1));
+        assert!(store.block_exists(&Id(5)).unwrap());
+        assert!(!store.block_exists(&Id(6)).unwrap());
+        assert!(matches!(
+            store.put_block(&TestBlock(7, 6, 4)),
+            Err(Error::MissingParent)
+        ));
+    }
+
+    #[test]
+    fn tags() {
+        let mut store = populated_store();
+        assert_eq!(store.get_tag("HEAD").unwrap(), None);
+        store.put_tag("HEAD", &Id(4)).unwrap();
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(Id(4)));
+        store.put_tag("HEAD", &Id(5)).unwrap();
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(Id(5)));
+        assert!(store.put_tag("HEAD", &Id(42)).is_err());
+    }
+
+    #[test]
+    fn ancestors() {
+        let mut store = MemoryBlockStore::new();
+        for block in linear_chain(1000) {
+            store.put_block(&block).unwrap();
+        }
+        store.put_block(&TestBlock(1001, 500, 500)).unwrap();
+        for depth in &[0, 1, 255, 256, 499, 500, 998, 999] {
+            let info = store.block_at_depth(&Id(1000), *depth).unwrap();
+            assert_eq!(info.block_hash, Id(*depth as u32 + 1));
+            assert_eq!(info.depth, *depth);
+        }
+        assert!(matches!(
+            store.block_at_depth(&Id(10), 10),
+            Err(Error::BlockNotFound)
+        ));
+        assert!(store.is_ancestor(&Id(1), &Id(1000)).unwrap());
+        assert!(store.is_ancestor(&Id(500), &Id(1001)).unwrap());
+        assert!(store.is_ancestor(&Id(1001), &Id(1001)).unwrap());
+        assert!(!store.is_ancestor(&Id(501), &Id(1001)).unwrap());
+        assert!(!store.is_ancestor(&Id(1000), &Id(10)).unwrap());
+    }
+
+    #[test]
+    fn prune() {
+        let mut store = MemoryBlockStore::new();
+        check_prune_forks(&mut store);
+        assert_eq!(store.blocks.len(), 6);
+    }
+
+    #[test]
+    fn stability() {
+        let store = populated_store();
+        assert_eq!(store.stable_tip(&Id(4), 2).unwrap().block_hash, Id(2));
+        assert_eq!(store.stable_tip(&Id(4), 0).unwrap().block_hash, Id(4));
+        assert_eq!(store.stable_tip(&Id(5), 10).unwrap().block_hash, Id(1));
+        assert!(store.is_stable(&Id(2), &Id(4), 2).unwrap());
+        assert!(!store.is_stable(&Id(3), &Id(4), 2).unwrap());
+        assert!(!store.is_stable(&Id(5), &Id(4), 0).unwrap());
+        assert!(store.is_stable(&Id(5), &Id(5), 0).unwrap());
+    }
+
+    #[test]
+    fn iterate() {
+        let store = populated_store();
+        assert_eq!(range_ids(&store, 1, 4), vec![2, 3, 4]);
+        assert_eq!(range_ids(&store, 2, 5), vec![5]);
+        assert_eq!(range_ids(&store, 4, 4), Vec::<u32>::new());
+        assert!(matches!(
+            store.iter_range(&Id(3), &Id(5)),
+            Err(Error::CannotIterate)
+        ));
+    }
+}
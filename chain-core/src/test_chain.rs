@@ -0,0 +1,183 @@
+//! A minimal chain for the tests of the code generic over the `property`
+//! traits.
+//!
+//! `TestBlock(id, parent, date)` is a block identified by its own id and
+//! its parent's; the date is also used as chain length and the block is
+//! its own header. All the fields are serialized as big endian `u32`.
+
+use crate::mempack::{ReadBuf, ReadError, Readable};
+use crate::property::{self, Block, BlockId, Deserialize, HasHeader, Serialize};
+use crate::store::{BlockStore, PRUNED_TAG};
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestBlock(pub u32, pub u32, pub u32);
+
+/// blocks `1 <- 2 <- ... <- length`, the date of each block being its
+/// depth
+pub fn linear_chain(length: u32) -> Vec<TestBlock> {
+    (1..=length).map(|i| TestBlock(i, i - 1, i - 1)).collect()
+}
+
+/// check the behavior of `BlockStore::prune_forks` on an empty store, so
+/// all the backends can be tested against the same scenario
+pub fn check_prune_forks<S: BlockStore<Block = TestBlock>>(store: &mut S) {
+    // 1 <- 2 <- 3 <- 4 <- 5 <- 6    12 <- 13
+    //       \    \    \
+    //        7    8    9 <- 10
+    //        |
+    //        11
+    for block in &[
+        TestBlock(1, 0, 0),
+        TestBlock(2, 1, 1),
+        TestBlock(3, 2, 2),
+        TestBlock(4, 3, 3),
+        TestBlock(5, 4, 4),
+        TestBlock(6, 5, 5),
+        TestBlock(7, 2, 2),
+        TestBlock(8, 3, 3),
+        TestBlock(9, 4, 4),
+        TestBlock(10, 9, 5),
+        TestBlock(11, 7, 3),
+        TestBlock(12, 0, 0),
+        TestBlock(13, 12, 1),
+    ] {
+        store.put_block(block).unwrap();
+    }
+    store.put_tag("fork", &Id(11)).unwrap();
+    let remaining = |store: &S| -> Vec<u32> {
+        (1..=13)
+            .filter(|id| store.block_exists(&Id(*id)).unwrap())
+            .collect()
+    };
+
+    // the other genesis block is removed with its descendants
+    assert_eq!(store.prune_forks(&Id(6), 4).unwrap(), 2);
+    assert_eq!(remaining(store), (1..=11).collect::<Vec<_>>());
+    assert_eq!(store.get_tag(PRUNED_TAG).unwrap(), Some(Id(2)));
+
+    assert_eq!(store.prune_forks(&Id(6), 3).unwrap(), 2);
+    assert_eq!(remaining(store), vec![1, 2, 3, 4, 5, 6, 8, 9, 10]);
+    assert_eq!(store.get_tag("fork").unwrap(), None);
+    assert_eq!(store.block_ids_at_depth(3).unwrap(), vec![Id(4), Id(8)]);
+
+    assert_eq!(store.prune_forks(&Id(6), 3).unwrap(), 0);
+    assert_eq!(store.prune_forks(&Id(6), 1).unwrap(), 3);
+    assert_eq!(remaining(store), vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(store.get_tag(PRUNED_TAG).unwrap(), Some(Id(5)));
+}
+
+fn read_u32<R: BufRead>(mut reader: R) -> Result<u32, io::Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+impl BlockId for Id {
+    fn zero() -> Self {
+        Id(0)
+    }
+}
+impl Serialize for Id {
+    type Error = io::Error;
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), io::Error> {
+        writer.write_all(&self.0.to_be_bytes())
+    }
+}
+impl Deserialize for Id {
+    type Error = io::Error;
+    fn deserialize<R: BufRead>(reader: R) -> Result<Self, io::Error> {
+        read_u32(reader).map(Id)
+    }
+}
+impl Readable for Id {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        buf.get_u32().map(Id)
+    }
+}
+
+impl property::BlockDate for Date {
+    fn from_epoch_slot_id(epoch: u32, slot_id: u32) -> Self {
+        Date(epoch * 100 + slot_id)
+    }
+}
+impl property::ChainLength for Date {
+    fn next(&self) -> Self {
+        Date(self.0 + 1)
+    }
+}
+
+impl Serialize for TestBlock {
+    type Error = io::Error;
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), io::Error> {
+        for v in &[self.0, self.1, self.2] {
+            writer.write_all(&v.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+impl Deserialize for TestBlock {
+    type Error = io::Error;
+    fn deserialize<R: BufRead>(mut reader: R) -> Result<Self, io::Error> {
+        Ok(TestBlock(
+            read_u32(&mut reader)?,
+            read_u32(&mut reader)?,
+            read_u32(&mut reader)?,
+        ))
+    }
+}
+impl Readable for TestBlock {
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        Ok(TestBlock(buf.get_u32()?, buf.get_u32()?, buf.get_u32()?))
+    }
+}
+impl property::Header for TestBlock {
+    type Id = Id;
+    type Date = Date;
+    type ChainLength = Date;
+    type Version = ();
+    fn id(&self) -> Id {
+        Id(self.0)
+    }
+    fn parent_id(&self) -> Id {
+        Id(self.1)
+    }
+    fn date(&self) -> Date {
+        Date(self.2)
+    }
+    fn version(&self) {}
+    fn chain_length(&self) -> Date {
+        Date(self.2)
+    }
+}
+impl Block for TestBlock {
+    type Id = Id;
+    type Date = Date;
+    type Version = ();
+    type ChainLength = Date;
+    fn id(&self) -> Id {
+        Id(self.0)
+    }
+    fn parent_id(&self) -> Id {
+        Id(self.1)
+    }
+    fn date(&self) -> Date {
+        Date(self.2)
+    }
+    fn version(&self) {}
+    fn chain_length(&self) -> Date {
+        Date(self.2)
+    }
+}
+impl HasHeader for TestBlock {
+    type Header = TestBlock;
+    fn header(&self) -> TestBlock {
+        *self
+    }
+}
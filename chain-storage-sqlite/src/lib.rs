@@ -0,0 +1,456 @@
+//! SQLite backend for the chain-core `BlockStore`
+//!
+//! Blocks are stored serialized, indexed by their hash and by their depth
+//! in the chain. Tags are stored in their own table.
+
+use chain_core::property::{Block, Deserialize, HasHeader, Serialize};
+use chain_core::store::{
+    first_unpruned_depth, BlockInfo, BlockStore, BlockStoreId, BlockStoreInfo, Error, PRUNED_TAG,
+};
+use rusqlite::{types::ToSql, Connection, OptionalExtension};
+use std::{marker::PhantomData, path::Path};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS blocks (
+        hash BLOB NOT NULL PRIMARY KEY,
+        parent BLOB NOT NULL,
+        depth INTEGER NOT NULL,
+        fast_ancestor BLOB NOT NULL,
+        block BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS blocks_depth ON blocks (depth);
+    CREATE INDEX IF NOT EXISTS blocks_parent ON blocks (parent);
+    CREATE TABLE IF NOT EXISTS tags (
+        name TEXT NOT NULL PRIMARY KEY,
+        hash BLOB NOT NULL
+    );
+";
+
+pub struct SQLiteBlockStore<B> {
+    connection: Connection,
+    dummy: PhantomData<B>,
+}
+
+fn backend_error<E>(e: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::BackendError(Box::new(e))
+}
+
+fn serialize<T: Serialize>(t: &T) -> Result<Vec<u8>, Error> {
+    t.serialize_as_vec()
+        .map_err(|e| Error::BackendError(e.to_string().into()))
+}
+
+fn deserialize<T: Deserialize>(bytes: &[u8]) -> Result<T, Error> {
+    T::deserialize(bytes).map_err(backend_error)
+}
+
+impl<B> SQLiteBlockStore<B>
+where
+    B: Block + HasHeader,
+{
+    /// open (or create) the store in the given database file
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(path).map_err(backend_error)?)
+    }
+
+    /// create a store in memory, nothing is persisted
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory().map_err(backend_error)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(SCHEMA).map_err(backend_error)?;
+        Ok(SQLiteBlockStore {
+            connection,
+            dummy: PhantomData,
+        })
+    }
+
+    /// store the given blocks, in order, within a single transaction
+    ///
+    /// Either all the blocks are stored or none of them are.
+    pub fn put_blocks<'a, I>(&mut self, blocks: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'a B>,
+        B: 'a,
+    {
+        self.connection
+            .execute_batch("BEGIN")
+            .map_err(backend_error)?;
+        let result = blocks
+            .into_iter()
+            .try_for_each(|block| self.put_block(block));
+        let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+        self.connection.execute_batch(end).map_err(backend_error)?;
+        result
+    }
+}
+
+impl<B> BlockStore for SQLiteBlockStore<B>
+where
+    B: Block + HasHeader,
+{
+    type Block = B;
+
+    fn put_block_internal(
+        &mut self,
+        block: &B,
+        block_info: BlockStoreInfo<Self>,
+    ) -> Result<(), Error> {
+        let hash = serialize(&block_info.block_hash)?;
+        let parent = serialize(&block_info.parent_id)?;
+        let fast_ancestor = serialize(&block_info.fast_ancestor)?;
+        let block = serialize(block)?;
+        self.connection
+            .prepare_cached(
+                "INSERT OR IGNORE INTO blocks (hash, parent, depth, fast_ancestor, block)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .and_then(|mut statement| {
+                statement.execute(&[
+                    &hash as &dyn ToSql,
+                    &parent,
+                    &(block_info.depth as i64),
+                    &fast_ancestor,
+                    &block,
+                ])
+            })
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<(B, BlockStoreInfo<Self>), Error> {
+        let hash = serialize(block_hash)?;
+        let (block, parent, depth, fast_ancestor) = self
+            .connection
+            .prepare_cached(
+                "SELECT block, parent, depth, fast_ancestor FROM blocks WHERE hash = ?1",
+            )
+            .and_then(|mut statement| {
+                statement.query_row(&[&hash as &dyn ToSql], |row| {
+                    Ok::<_, rusqlite::Error>((
+                        row.get_checked::<_, Vec<u8>>(0)?,
+                        row.get_checked::<_, Vec<u8>>(1)?,
+                        row.get_checked::<_, i64>(2)?,
+                        row.get_checked::<_, Vec<u8>>(3)?,
+                    ))
+                })
+            })
+            .optional()
+            .map_err(backend_error)?
+            .ok_or(Error::BlockNotFound)?
+            .map_err(backend_error)?;
+        let info = BlockInfo {
+            block_hash: block_hash.clone(),
+            parent_id: deserialize(&parent)?,
+            depth: depth as u64,
+            fast_ancestor: deserialize(&fast_ancestor)?,
+        };
+        Ok((deserialize(&block)?, info))
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &BlockStoreId<Self>,
+    ) -> Result<BlockStoreInfo<Self>, Error> {
+        let hash = serialize(block_hash)?;
+        let (parent, depth, fast_ancestor) = self
+            .connection
+            .prepare_cached("SELECT parent, depth, fast_ancestor FROM blocks WHERE hash = ?1")
+            .and_then(|mut statement| {
+                statement.query_row(&[&hash as &dyn ToSql], |row| {
+                    Ok::<_, rusqlite::Error>((
+                        row.get_checked::<_, Vec<u8>>(0)?,
+                        row.get_checked::<_, i64>(1)?,
+                        row.get_checked::<_, Vec<u8>>(2)?,
+                    ))
+                })
+            })
+            .optional()
+            .map_err(backend_error)?
+            .ok_or(Error::BlockNotFound)?
+            .map_err(backend_error)?;
+        Ok(BlockInfo {
+            block_hash: block_hash.clone(),
+            parent_id: deserialize(&parent)?,
+            depth: depth as u64,
+            fast_ancestor: deserialize(&fast_ancestor)?,
+        })
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &BlockStoreId<Self>) -> Result<(), Error> {
+        if !self.block_exists(block_hash)? {
+            return Err(Error::BlockNotFound);
+        }
+        let hash = serialize(block_hash)?;
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO tags (name, hash) VALUES (?1, ?2)",
+                &[&tag_name as &dyn ToSql, &hash],
+            )
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<BlockStoreId<Self>>, Error> {
+        let hash = self
+            .connection
+            .query_row(
+                "SELECT hash FROM tags WHERE name = ?1",
+                &[&tag_name as &dyn ToSql],
+                |row| row.get_checked::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(backend_error)?;
+        match hash {
+            None => Ok(None),
+            Some(hash) => Ok(Some(deserialize(&hash.map_err(backend_error)?)?)),
+        }
+    }
+
+    fn block_ids_at_depth(&self, depth: u64) -> Result<Vec<BlockStoreId<Self>>, Error> {
+        let mut statement = self
+            .connection
+            .prepare_cached("SELECT hash FROM blocks WHERE depth = ?1 ORDER BY hash")
+            .map_err(backend_error)?;
+        let rows = statement
+            .query_map(&[&(depth as i64) as &dyn ToSql], |row| {
+                row.get_checked::<_, Vec<u8>>(0)
+            })
+            .map_err(backend_error)?;
+        rows.map(|hash| deserialize(&hash.map_err(backend_error)?.map_err(backend_error)?))
+            .collect()
+    }
+
+    fn remove_block_internal(&mut self, block_hash: &BlockStoreId<Self>) -> Result<(), Error> {
+        let hash = serialize(block_hash)?;
+        let removed = self
+            .connection
+            .execute("DELETE FROM blocks WHERE hash = ?1", &[&hash as &dyn ToSql])
+            .map_err(backend_error)?;
+        if removed == 0 {
+            return Err(Error::BlockNotFound);
+        }
+        self.connection
+            .execute("DELETE FROM tags WHERE hash = ?1", &[&hash as &dyn ToSql])
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    /// same as the default implementation, but the whole range is read by
+    /// a single query
+    fn range_ids(
+        &self,
+        from: &BlockStoreId<Self>,
+        to: &BlockStoreId<Self>,
+    ) -> Result<Vec<BlockStoreId<Self>>, Error> {
+        let from_depth = self.get_block_info(from)?.depth;
+        let to = serialize(to)?;
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "WITH RECURSIVE
+                    range(hash, parent, depth) AS (
+                        SELECT hash, parent, depth FROM blocks WHERE hash = ?1
+                        UNION ALL
+                        SELECT blocks.hash, blocks.parent, blocks.depth FROM blocks
+                        JOIN range ON blocks.hash = range.parent
+                        WHERE range.depth > ?2
+                    )
+                SELECT hash FROM range ORDER BY depth",
+            )
+            .map_err(backend_error)?;
+        let rows = statement
+            .query_map(&[&to as &dyn ToSql, &(from_depth as i64)], |row| {
+                row.get_checked::<_, Vec<u8>>(0)
+            })
+            .map_err(backend_error)?;
+        let mut ids = rows
+            .map(|hash| deserialize(&hash.map_err(backend_error)?.map_err(backend_error)?))
+            .collect::<Result<Vec<BlockStoreId<Self>>, Error>>()?
+            .into_iter();
+        // the first block is the ancestor of `to` at the depth of `from`,
+        // or `to` itself if it is below `from`
+        match ids.next() {
+            None => Err(Error::BlockNotFound),
+            Some(ref first) if first == from => Ok(ids.collect()),
+            Some(_) => Err(Error::CannotIterate),
+        }
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.connection
+            .execute_batch("VACUUM")
+            .map_err(backend_error)
+    }
+
+    /// same as the default implementation, but the dead branches are found
+    /// and removed by a single query
+    fn prune_forks(
+        &mut self,
+        tip: &BlockStoreId<Self>,
+        stability_depth: u64,
+    ) -> Result<usize, Error> {
+        let stable_tip = self.stable_tip(tip, stability_depth)?;
+        let first_depth = first_unpruned_depth(self, &stable_tip.block_hash)?;
+        let tip = serialize(tip)?;
+        self.connection
+            .execute_batch("BEGIN")
+            .map_err(backend_error)?;
+        let result = self
+            .connection
+            .execute(
+                "WITH RECURSIVE
+                    main(hash, parent, depth) AS (
+                        SELECT hash, parent, depth FROM blocks WHERE hash = ?1
+                        UNION ALL
+                        SELECT blocks.hash, blocks.parent, blocks.depth FROM blocks
+                        JOIN main ON blocks.hash = main.parent
+                        WHERE main.depth > ?3
+                    ),
+                    dead(hash) AS (
+                        SELECT hash FROM blocks
+                        WHERE depth >= ?3 AND depth <= ?2
+                            AND hash NOT IN (SELECT hash FROM main)
+                        UNION
+                        SELECT blocks.hash FROM blocks
+                        JOIN dead ON blocks.parent = dead.hash
+                    )
+                DELETE FROM blocks WHERE hash IN (SELECT hash FROM dead)",
+                &[
+                    &tip as &dyn ToSql,
+                    &(stable_tip.depth as i64),
+                    &(first_depth as i64),
+                ],
+            )
+            .and_then(|removed| {
+                self.connection
+                    .execute(
+                        "DELETE FROM tags WHERE hash NOT IN (SELECT hash FROM blocks)",
+                        &[] as &[&dyn ToSql],
+                    )
+                    .map(|_| removed)
+            })
+            .map_err(backend_error)
+            .and_then(|removed| {
+                self.put_tag(PRUNED_TAG, &stable_tip.block_hash)
+                    .map(|()| removed)
+            });
+        let end = if result.is_ok() { "COMMIT" } else { "ROLLBACK" };
+        self.connection.execute_batch(end).map_err(backend_error)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_core::test_chain::{check_prune_forks, Id, TestBlock};
+
+    /// main chain 1 <- 2 <- 3 <- 4 <- 5, forks 2 <- 6 <- 7 and 3 <- 8
+    const BLOCKS: [TestBlock; 8] = [
+        TestBlock(1, 0, 0),
+        TestBlock(2, 1, 1),
+        TestBlock(3, 2, 2),
+        TestBlock(4, 3, 3),
+        TestBlock(5, 4, 4),
+        TestBlock(6, 2, 2),
+        TestBlock(7, 6, 3),
+        TestBlock(8, 3, 3),
+    ];
+
+    fn populated_store() -> SQLiteBlockStore<TestBlock> {
+        let mut store = SQLiteBlockStore::in_memory().unwrap();
+        store.put_blocks(&BLOCKS).unwrap();
+        store
+    }
+
+    #[test]
+    fn put_and_get() {
+        let store = populated_store();
+        for block in BLOCKS.iter() {
+            let (stored, info) = store.get_block(&block.id()).unwrap();
+            assert_eq!(&stored, block);
+            assert_eq!(info.parent_id, block.parent_id());
+            assert_eq!(info.depth, block.2 as u64);
+        }
+        assert!(matches!(
+            store.get_block(&Id(42)),
+            Err(Error::BlockNotFound)
+        ));
+        assert_eq!(
+            store.block_ids_at_depth(3).unwrap(),
+            vec![Id(4), Id(7), Id(8)]
+        );
+    }
+
+    #[test]
+    fn batch_is_atomic() {
+        let mut store = SQLiteBlockStore::in_memory().unwrap();
+        let blocks = [TestBlock(1, 0, 0), TestBlock(2, 1, 1), TestBlock(4, 3, 3)];
+        assert!(matches!(
+            store.put_blocks(&blocks),
+            Err(Error::MissingParent)
+        ));
+        assert!(!store.block_exists(&Id(1)).unwrap());
+        store.put_blocks(&blocks[..2]).unwrap();
+        assert!(store.block_exists(&Id(2)).unwrap());
+    }
+
+    #[test]
+    fn tags() {
+        let mut store = populated_store();
+        assert_eq!(store.get_tag("HEAD").unwrap(), None);
+        store.put_tag("HEAD", &Id(5)).unwrap();
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(Id(5)));
+        store.put_tag("HEAD", &Id(7)).unwrap();
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(Id(7)));
+        assert!(store.put_tag("HEAD", &Id(42)).is_err());
+    }
+
+    #[test]
+    fn iterate() {
+        let store = populated_store();
+        let range_ids = |from, to| -> Vec<u32> {
+            store
+                .iter_range(&Id(from), &Id(to))
+                .unwrap()
+                .map(|r| r.unwrap().0 .0)
+                .collect()
+        };
+        assert_eq!(range_ids(1, 5), vec![2, 3, 4, 5]);
+        assert_eq!(range_ids(2, 7), vec![6, 7]);
+        assert_eq!(range_ids(3, 3), Vec::<u32>::new());
+        for (from, to) in &[(6, 5), (5, 3), (8, 5)] {
+            assert!(matches!(
+                store.iter_range(&Id(*from), &Id(*to)),
+                Err(Error::CannotIterate)
+            ));
+        }
+        assert!(matches!(
+            store.iter_range(&Id(1), &Id(42)),
+            Err(Error::BlockNotFound)
+        ));
+    }
+
+    #[test]
+    fn prune() {
+        let mut store = SQLiteBlockStore::in_memory().unwrap();
+        check_prune_forks(&mut store);
+        store.compact().unwrap();
+        assert_eq!(
+            store
+                .iter_range(&Id(1), &Id(6))
+                .unwrap()
+                .map(|r| r.unwrap().0 .0)
+                .collect::<Vec<_>>(),
+            vec![2, 3, 4, 5, 6]
+        );
+    }
+}
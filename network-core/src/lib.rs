@@ -8,4 +8,10 @@ pub mod client;
 pub mod server;
 
 pub mod gossip;
+pub mod message;
+pub mod policy;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod relay;
 pub mod subscription;
+pub mod sync;
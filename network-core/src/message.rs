@@ -0,0 +1,355 @@
+//! Wire messages of the node to node protocol.
+//!
+//! Every message starts with a one byte tag identifying its kind.
+//! Headers, blocks and transactions are preceded by their length in bytes
+//! (`u32`), so the receiver can delimit them without knowing their
+//! encoding. Block identifiers are written as is and must be
+//! self-delimiting.
+
+use chain_core::{
+    mempack::{ReadBuf, ReadError, Readable},
+    packer::Codec,
+    property::{Block, HasHeader, Serialize},
+};
+
+use std::{
+    error,
+    fmt::{self, Debug},
+    io,
+};
+
+/// Version of the protocol implemented by this module.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+const TAG_HANDSHAKE: u8 = 1;
+const TAG_ANNOUNCE_HEADER: u8 = 2;
+const TAG_GET_BLOCKS: u8 = 3;
+const TAG_BLOCK: u8 = 4;
+const TAG_PUSH_TRANSACTION: u8 = 5;
+
+/// First message sent by each peer on a new connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake<Id> {
+    /// version of the protocol spoken by the peer
+    pub version: u16,
+    /// identifier of the genesis block of the peer's chain
+    pub genesis: Id,
+}
+
+/// Reasons for refusing the handshake of a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// the peer speaks another version of the protocol
+    UnsupportedVersion(u16),
+    /// the peer follows a chain with another genesis block
+    GenesisMismatch,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandshakeError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported protocol version {}, expected {}",
+                v, PROTOCOL_VERSION
+            ),
+            HandshakeError::GenesisMismatch => write!(f, "peer uses a different genesis block"),
+        }
+    }
+}
+
+impl error::Error for HandshakeError {}
+
+impl<Id: PartialEq> Handshake<Id> {
+    /// handshake for the current protocol version
+    pub fn new(genesis: Id) -> Self {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            genesis,
+        }
+    }
+
+    /// check the peer's handshake is compatible with our genesis block
+    pub fn check(&self, genesis: &Id) -> Result<(), HandshakeError> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(HandshakeError::UnsupportedVersion(self.version));
+        }
+        if &self.genesis != genesis {
+            return Err(HandshakeError::GenesisMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Messages exchanged between nodes.
+pub enum Message<B, T>
+where
+    B: Block + HasHeader,
+{
+    /// protocol version and genesis block of the sender
+    Handshake(Handshake<<B as Block>::Id>),
+    /// a new block is available from the sender
+    AnnounceHeader(<B as HasHeader>::Header),
+    /// request the identified blocks
+    GetBlocks(Vec<<B as Block>::Id>),
+    /// a block, sent in response to `GetBlocks`
+    Block(B),
+    /// a transaction to relay and add to the mempool
+    PushTransaction(T),
+}
+
+impl<B, T> Debug for Message<B, T>
+where
+    B: Block + HasHeader + Debug,
+    <B as HasHeader>::Header: Debug,
+    T: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Handshake(h) => f.debug_tuple("Handshake").field(h).finish(),
+            Message::AnnounceHeader(h) => f.debug_tuple("AnnounceHeader").field(h).finish(),
+            Message::GetBlocks(ids) => f.debug_tuple("GetBlocks").field(ids).finish(),
+            Message::Block(b) => f.debug_tuple("Block").field(b).finish(),
+            Message::PushTransaction(t) => f.debug_tuple("PushTransaction").field(t).finish(),
+        }
+    }
+}
+
+impl<B, T> Clone for Message<B, T>
+where
+    B: Block + HasHeader + Clone,
+    <B as HasHeader>::Header: Clone,
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Message::Handshake(h) => Message::Handshake(h.clone()),
+            Message::AnnounceHeader(h) => Message::AnnounceHeader(h.clone()),
+            Message::GetBlocks(ids) => Message::GetBlocks(ids.clone()),
+            Message::Block(b) => Message::Block(b.clone()),
+            Message::PushTransaction(t) => Message::PushTransaction(t.clone()),
+        }
+    }
+}
+
+impl<B, T> PartialEq for Message<B, T>
+where
+    B: Block + HasHeader + PartialEq,
+    <B as HasHeader>::Header: PartialEq,
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Message::Handshake(a), Message::Handshake(b)) => a == b,
+            (Message::AnnounceHeader(a), Message::AnnounceHeader(b)) => a == b,
+            (Message::GetBlocks(a), Message::GetBlocks(b)) => a == b,
+            (Message::Block(a), Message::Block(b)) => a == b,
+            (Message::PushTransaction(a), Message::PushTransaction(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+fn write_sized<W: io::Write, S: Serialize>(codec: &mut Codec<W>, s: &S) -> io::Result<()> {
+    use std::io::Write;
+
+    let bytes = s
+        .serialize_as_vec()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if bytes.len() > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message payload too large",
+        ));
+    }
+    codec.put_u32(bytes.len() as u32)?;
+    codec.write_all(&bytes)
+}
+
+fn read_sized<'a, R: Readable>(buf: &mut ReadBuf<'a>, context: &str) -> Result<R, ReadError> {
+    let len = buf.get_u32()? as usize;
+    let mut sub = buf.split_to(len)?;
+    sub.with_context(context, |sub| {
+        let r = R::read(sub)?;
+        sub.expect_end()?;
+        Ok(r)
+    })
+}
+
+impl<B, T> Serialize for Message<B, T>
+where
+    B: Block + HasHeader + Serialize,
+    T: Serialize,
+{
+    type Error = io::Error;
+
+    fn serialize<W: io::Write>(&self, writer: W) -> Result<(), Self::Error> {
+        let mut codec = Codec::new(writer);
+        match self {
+            Message::Handshake(handshake) => {
+                codec.put_u8(TAG_HANDSHAKE)?;
+                codec.put_u16(handshake.version)?;
+                handshake
+                    .genesis
+                    .serialize(&mut codec)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            }
+            Message::AnnounceHeader(header) => {
+                codec.put_u8(TAG_ANNOUNCE_HEADER)?;
+                write_sized(&mut codec, header)?;
+            }
+            Message::GetBlocks(ids) => {
+                codec.put_u8(TAG_GET_BLOCKS)?;
+                if ids.len() > u16::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "too many block identifiers",
+                    ));
+                }
+                codec.put_u16(ids.len() as u16)?;
+                for id in ids {
+                    id.serialize(&mut codec)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                }
+            }
+            Message::Block(block) => {
+                codec.put_u8(TAG_BLOCK)?;
+                write_sized(&mut codec, block)?;
+            }
+            Message::PushTransaction(tx) => {
+                codec.put_u8(TAG_PUSH_TRANSACTION)?;
+                write_sized(&mut codec, tx)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B, T> Readable for Message<B, T>
+where
+    B: Block + HasHeader + Readable,
+    <B as Block>::Id: Readable,
+    <B as HasHeader>::Header: Readable,
+    T: Readable,
+{
+    fn read<'a>(buf: &mut ReadBuf<'a>) -> Result<Self, ReadError> {
+        match buf.get_u8()? {
+            TAG_HANDSHAKE => {
+                let version = buf.get_u16()?;
+                let genesis = Readable::read(buf)?;
+                Ok(Message::Handshake(Handshake { version, genesis }))
+            }
+            TAG_ANNOUNCE_HEADER => read_sized(buf, "header").map(Message::AnnounceHeader),
+            TAG_GET_BLOCKS => {
+                let count = buf.get_u16()? as usize;
+                // block identifiers are never empty
+                buf.check_count(count, 1)?;
+                let mut ids = Vec::with_capacity(count);
+                for i in 0..count {
+                    ids.push(buf.with_context(format_args!("block_id[{}]", i), Readable::read)?);
+                }
+                Ok(Message::GetBlocks(ids))
+            }
+            TAG_BLOCK => read_sized(buf, "block").map(Message::Block),
+            TAG_PUSH_TRANSACTION => read_sized(buf, "transaction").map(Message::PushTransaction),
+            tag => Err(ReadError::UnknownTag(tag as u32)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_core::test_chain::{Id, TestBlock};
+
+    type TestMessage = Message<TestBlock, TestBlock>;
+
+    fn roundtrip(message: TestMessage) {
+        let bytes = message.serialize_as_vec().unwrap();
+        let mut buf = ReadBuf::from(&bytes);
+        let decoded = TestMessage::read(&mut buf).unwrap();
+        buf.expect_end().unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        roundtrip(Message::Handshake(Handshake::new(Id(1))));
+        roundtrip(Message::AnnounceHeader(TestBlock(3, 2, 2)));
+        roundtrip(Message::GetBlocks(vec![]));
+        roundtrip(Message::GetBlocks(vec![Id(3), Id(4)]));
+        roundtrip(Message::Block(TestBlock(3, 2, 2)));
+        roundtrip(Message::PushTransaction(TestBlock(7, 0, 0)));
+    }
+
+    #[test]
+    fn message_encoding() {
+        let message: TestMessage = Message::Block(TestBlock(3, 2, 1));
+        assert_eq!(
+            message.serialize_as_vec().unwrap(),
+            vec![TAG_BLOCK, 0, 0, 0, 12, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 1]
+        );
+        let mut buf = ReadBuf::from(&[0xaa]);
+        assert_eq!(
+            TestMessage::read(&mut buf).unwrap_err(),
+            ReadError::UnknownTag(0xaa)
+        );
+
+        let bytes = [TAG_GET_BLOCKS, 0, 2, 0, 0, 0, 1, 0, 0];
+        let err = TestMessage::read(&mut ReadBuf::from(&bytes)).unwrap_err();
+        assert_eq!(err.root(), &ReadError::NotEnoughBytes(2, 4));
+        assert_eq!(err.offset(), Some(7));
+        assert_eq!(err.path(), &["block_id[1]".to_string()]);
+
+        let bytes = [TAG_BLOCK, 0, 0, 0, 10, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0];
+        let err = TestMessage::read(&mut ReadBuf::from(&bytes)).unwrap_err();
+        assert_eq!(err.root(), &ReadError::NotEnoughBytes(2, 4));
+        assert_eq!(err.offset(), Some(13));
+        assert_eq!(err.path(), &["block".to_string()]);
+
+        let bytes = [TAG_GET_BLOCKS, 0xff, 0xff, 0, 0, 0, 1];
+        assert_eq!(
+            TestMessage::read(&mut ReadBuf::from(&bytes)).unwrap_err(),
+            ReadError::SizeTooBig(0xffff, 4)
+        );
+    }
+
+    #[test]
+    fn message_golden() {
+        let cases: &[(&str, TestMessage)] = &[
+            ("handshake", Message::Handshake(Handshake::new(Id(1)))),
+            (
+                "announce_header",
+                Message::AnnounceHeader(TestBlock(3, 2, 2)),
+            ),
+            ("get_blocks_empty", Message::GetBlocks(vec![])),
+            ("get_blocks", Message::GetBlocks(vec![Id(3), Id(4)])),
+            ("block", Message::Block(TestBlock(3, 2, 2))),
+            (
+                "push_transaction",
+                Message::PushTransaction(TestBlock(7, 0, 0)),
+            ),
+        ];
+        chain_core::golden::check(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/message.txt"),
+            cases,
+        );
+    }
+
+    #[test]
+    fn handshake_check() {
+        assert_eq!(Handshake::new(Id(1)).check(&Id(1)), Ok(()));
+        assert_eq!(
+            Handshake::new(Id(1)).check(&Id(2)),
+            Err(HandshakeError::GenesisMismatch)
+        );
+        let old = Handshake {
+            version: 0,
+            genesis: Id(1),
+        };
+        assert_eq!(
+            old.check(&Id(1)),
+            Err(HandshakeError::UnsupportedVersion(0))
+        );
+    }
+}
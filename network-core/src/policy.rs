@@ -0,0 +1,286 @@
+//! Scoring of the misbehavior of peers.
+//!
+//! `PeerPolicy` keeps a score for every peer that sent invalid data or
+//! violated the protocol. Every misbehavior adds a penalty to the score
+//! of the peer, and the score slowly recovers over time. The networking
+//! layer asks the policy what to do with a peer: accept its requests,
+//! throttle it, or ban it for some time.
+//!
+//! The current time is passed explicitly to all the methods so the
+//! policy does not depend on a clock.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Misbehaviors attributable to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Misbehavior {
+    /// the peer sent a block that failed validation
+    InvalidBlock,
+    /// the peer sent a transaction that failed validation
+    InvalidTransaction,
+    /// the peer sent a malformed or unexpected message
+    ProtocolViolation,
+}
+
+/// What to do with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Decision {
+    /// the peer behaves well enough
+    Accept,
+    /// limit the rate of the requests of the peer
+    Throttle,
+    /// disconnect the peer and refuse its connections
+    Ban,
+}
+
+/// Parameters of the `PeerPolicy`.
+#[derive(Debug, Clone)]
+pub struct PolicyConfig {
+    pub invalid_block_penalty: u32,
+    pub invalid_transaction_penalty: u32,
+    pub protocol_violation_penalty: u32,
+    /// score from which the peer is throttled
+    pub throttle_threshold: u32,
+    /// score from which the peer is banned
+    pub ban_threshold: u32,
+    /// how long a peer stays banned
+    pub ban_duration: Duration,
+    /// time for the score to recover by one point
+    pub recovery_interval: Duration,
+}
+
+impl PolicyConfig {
+    pub fn penalty(&self, misbehavior: Misbehavior) -> u32 {
+        match misbehavior {
+            Misbehavior::InvalidBlock => self.invalid_block_penalty,
+            Misbehavior::InvalidTransaction => self.invalid_transaction_penalty,
+            Misbehavior::ProtocolViolation => self.protocol_violation_penalty,
+        }
+    }
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            invalid_block_penalty: 50,
+            invalid_transaction_penalty: 10,
+            protocol_violation_penalty: 25,
+            throttle_threshold: 50,
+            ban_threshold: 100,
+            ban_duration: Duration::from_secs(3600),
+            recovery_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PeerRecord {
+    score: u32,
+    /// time from which the recovery of the score is counted
+    updated: Instant,
+    banned_until: Option<Instant>,
+}
+
+impl PeerRecord {
+    /// bring the score up to date with the recovery since the last update
+    fn recover(&mut self, now: Instant, interval: Duration) {
+        if now <= self.updated || interval == Duration::from_secs(0) {
+            return;
+        }
+        let elapsed = now - self.updated;
+        let points = elapsed.as_nanos() / interval.as_nanos();
+        if points == 0 {
+            return;
+        }
+        if points >= u128::from(self.score) {
+            self.score = 0;
+            self.updated = now;
+        } else {
+            self.score -= points as u32;
+            self.updated += interval * points as u32;
+        }
+    }
+
+    fn is_banned(&self, now: Instant) -> bool {
+        match self.banned_until {
+            Some(until) => now < until,
+            None => false,
+        }
+    }
+
+    fn decision(&self, config: &PolicyConfig, now: Instant) -> Decision {
+        if self.is_banned(now) {
+            Decision::Ban
+        } else if self.score >= config.throttle_threshold {
+            Decision::Throttle
+        } else {
+            Decision::Accept
+        }
+    }
+}
+
+/// Misbehavior scores of the peers, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct PeerPolicy<Id> {
+    config: PolicyConfig,
+    peers: HashMap<Id, PeerRecord>,
+}
+
+impl<Id: Eq + Hash + Clone> PeerPolicy<Id> {
+    pub fn new(config: PolicyConfig) -> Self {
+        PeerPolicy {
+            config,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PolicyConfig {
+        &self.config
+    }
+
+    /// record a misbehavior of the peer and return the decision for the
+    /// peer with its new score
+    ///
+    /// Reaching the ban threshold bans the peer for the configured
+    /// duration, from `now`.
+    pub fn record(&mut self, peer: &Id, misbehavior: Misbehavior, now: Instant) -> Decision {
+        let penalty = self.config.penalty(misbehavior);
+        let record = self.peers.entry(peer.clone()).or_insert(PeerRecord {
+            score: 0,
+            updated: now,
+            banned_until: None,
+        });
+        record.recover(now, self.config.recovery_interval);
+        record.score = record.score.saturating_add(penalty);
+        if record.score >= self.config.ban_threshold {
+            record.banned_until = Some(now + self.config.ban_duration);
+        }
+        record.decision(&self.config, now)
+    }
+
+    /// current score of the peer, 0 for unknown peers
+    pub fn score(&self, peer: &Id, now: Instant) -> u32 {
+        match self.peers.get(peer) {
+            None => 0,
+            Some(record) => {
+                let mut record = record.clone();
+                record.recover(now, self.config.recovery_interval);
+                record.score
+            }
+        }
+    }
+
+    /// decision for the peer
+    pub fn decision(&self, peer: &Id, now: Instant) -> Decision {
+        match self.peers.get(peer) {
+            None => Decision::Accept,
+            Some(record) => {
+                let mut record = record.clone();
+                record.recover(now, self.config.recovery_interval);
+                record.decision(&self.config, now)
+            }
+        }
+    }
+
+    /// forget everything about the peer, lifting any ban
+    pub fn forget(&mut self, peer: &Id) {
+        self.peers.remove(peer);
+    }
+
+    /// remove the records of the peers that are not banned and whose
+    /// score has fully recovered
+    pub fn prune(&mut self, now: Instant) {
+        let interval = self.config.recovery_interval;
+        self.peers.retain(|_, record| {
+            record.recover(now, interval);
+            record.score > 0 || record.is_banned(now)
+        });
+    }
+
+    /// number of peers with a record
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+impl<Id: Eq + Hash + Clone> Default for PeerPolicy<Id> {
+    fn default() -> Self {
+        PeerPolicy::new(PolicyConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(m: u64) -> Duration {
+        Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn score_and_decisions() {
+        let start = Instant::now();
+        let mut policy = PeerPolicy::default();
+        assert_eq!(policy.decision(&1, start), Decision::Accept);
+        assert_eq!(
+            policy.record(&1, Misbehavior::InvalidTransaction, start),
+            Decision::Accept
+        );
+        assert_eq!(
+            policy.record(&1, Misbehavior::InvalidBlock, start),
+            Decision::Throttle
+        );
+        assert_eq!(policy.score(&1, start), 60);
+        assert_eq!(policy.score(&2, start), 0);
+
+        // the score recovers by one point per minute
+        assert_eq!(policy.score(&1, start + minutes(15)), 45);
+        assert_eq!(policy.decision(&1, start + minutes(15)), Decision::Accept);
+        assert_eq!(policy.score(&1, start + minutes(100)), 0);
+
+        assert_eq!(
+            policy.record(&1, Misbehavior::ProtocolViolation, start + minutes(10)),
+            Decision::Throttle
+        );
+        assert_eq!(policy.score(&1, start + minutes(10)), 75);
+    }
+
+    #[test]
+    fn ban_expires() {
+        let start = Instant::now();
+        let mut policy = PeerPolicy::default();
+        policy.record(&1, Misbehavior::InvalidBlock, start);
+        assert_eq!(
+            policy.record(&1, Misbehavior::InvalidBlock, start),
+            Decision::Ban
+        );
+        assert_eq!(policy.decision(&1, start + minutes(59)), Decision::Ban);
+        assert_eq!(policy.decision(&1, start + minutes(60)), Decision::Accept);
+        assert_eq!(policy.score(&1, start + minutes(60)), 40);
+
+        policy.forget(&1);
+        assert_eq!(policy.decision(&1, start), Decision::Accept);
+    }
+
+    #[test]
+    fn prune_recovered_peers() {
+        let start = Instant::now();
+        let mut policy = PeerPolicy::default();
+        policy.record(&1, Misbehavior::InvalidTransaction, start);
+        policy.record(&2, Misbehavior::InvalidBlock, start);
+        policy.record(&2, Misbehavior::InvalidBlock, start);
+        policy.prune(start + minutes(10));
+        assert_eq!(policy.len(), 1);
+        assert_eq!(policy.score(&2, start + minutes(10)), 90);
+        policy.prune(start + minutes(100));
+        assert!(policy.is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! Protobuf representation of the network protocol types.
+//!
+//! The protobuf messages carry blocks, headers and transactions as opaque
+//! bytes in their chain specific binary encoding, so the same definitions
+//! work for any chain implementing the `chain_core::property` traits.
+//! The `FromProtobuf` and `IntoProtobuf` traits convert between the
+//! protobuf messages and the chain types.
+//!
+//! Block uploads and downloads are streams of messages; on a byte stream
+//! each message is framed by its length encoded as a varint, as done by
+//! the protobuf libraries for delimited messages.
+
+use crate::{
+    error::{Code, Error},
+    message,
+};
+
+use chain_core::{
+    mempack::{ReadBuf, Readable},
+    property,
+};
+
+use std::io;
+
+/// A block in its binary encoding.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Block {
+    #[prost(bytes, tag = "1")]
+    pub content: Vec<u8>,
+}
+
+/// A block header in its binary encoding.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Header {
+    #[prost(bytes, tag = "1")]
+    pub content: Vec<u8>,
+}
+
+/// A transaction in its binary encoding.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Transaction {
+    #[prost(bytes, tag = "1")]
+    pub content: Vec<u8>,
+}
+
+/// A list of block identifiers in their binary encoding.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BlockIds {
+    #[prost(bytes, repeated, tag = "1")]
+    pub ids: Vec<Vec<u8>>,
+}
+
+/// The handshake exchanged when a connection is established.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Handshake {
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    #[prost(bytes, tag = "2")]
+    pub genesis: Vec<u8>,
+}
+
+/// Conversion from a protobuf message.
+pub trait FromProtobuf<P>: Sized {
+    fn from_message(message: P) -> Result<Self, Error>;
+}
+
+/// Conversion into a protobuf message.
+pub trait IntoProtobuf<P> {
+    fn into_message(self) -> Result<P, Error>;
+}
+
+/// serialize the object into the bytes carried by a protobuf message
+pub fn serialize_to_bytes<T: property::Serialize>(obj: &T) -> Result<Vec<u8>, Error> {
+    obj.serialize_as_vec()
+        .map_err(|e| Error::new(Code::Internal, e.to_string()))
+}
+
+/// deserialize the bytes carried by a protobuf message, all the bytes
+/// must be consumed
+pub fn deserialize_bytes<T: property::Deserialize>(mut bytes: &[u8]) -> Result<T, Error> {
+    let obj = T::deserialize(&mut bytes).map_err(|e| Error::new(Code::InvalidArgument, e))?;
+    if !bytes.is_empty() {
+        return Err(Error::new(
+            Code::InvalidArgument,
+            "trailing bytes after the decoded object",
+        ));
+    }
+    Ok(obj)
+}
+
+/// read the bytes carried by a protobuf message, for the types that are
+/// only `Readable`; all the bytes must be consumed
+pub fn read_bytes<T: Readable>(bytes: &[u8]) -> Result<T, Error> {
+    let mut buf = ReadBuf::from(bytes);
+    let obj = T::read(&mut buf).map_err(|e| Error::new(Code::InvalidArgument, e))?;
+    buf.expect_end()
+        .map_err(|e| Error::new(Code::InvalidArgument, e))?;
+    Ok(obj)
+}
+
+impl<B: property::Block> IntoProtobuf<Block> for &B {
+    fn into_message(self) -> Result<Block, Error> {
+        let content = serialize_to_bytes(self)?;
+        Ok(Block { content })
+    }
+}
+
+impl<B: property::Block> FromProtobuf<Block> for B {
+    fn from_message(message: Block) -> Result<Self, Error> {
+        deserialize_bytes(&message.content)
+    }
+}
+
+impl<H: property::Header> IntoProtobuf<Header> for &H {
+    fn into_message(self) -> Result<Header, Error> {
+        let content = serialize_to_bytes(self)?;
+        Ok(Header { content })
+    }
+}
+
+impl<H: property::Header + Readable> FromProtobuf<Header> for H {
+    fn from_message(message: Header) -> Result<Self, Error> {
+        read_bytes(&message.content)
+    }
+}
+
+impl<T: property::Transaction> IntoProtobuf<Transaction> for &T {
+    fn into_message(self) -> Result<Transaction, Error> {
+        let content = serialize_to_bytes(self)?;
+        Ok(Transaction { content })
+    }
+}
+
+impl<T: property::Transaction> FromProtobuf<Transaction> for T {
+    fn from_message(message: Transaction) -> Result<Self, Error> {
+        deserialize_bytes(&message.content)
+    }
+}
+
+impl<Id: property::BlockId> IntoProtobuf<BlockIds> for &[Id] {
+    fn into_message(self) -> Result<BlockIds, Error> {
+        let ids = self
+            .iter()
+            .map(serialize_to_bytes)
+            .collect::<Result<_, _>>()?;
+        Ok(BlockIds { ids })
+    }
+}
+
+impl<Id: property::BlockId> FromProtobuf<BlockIds> for Vec<Id> {
+    fn from_message(message: BlockIds) -> Result<Self, Error> {
+        message
+            .ids
+            .iter()
+            .map(|bytes| deserialize_bytes(bytes))
+            .collect()
+    }
+}
+
+impl<Id: property::BlockId> IntoProtobuf<Handshake> for &message::Handshake<Id> {
+    fn into_message(self) -> Result<Handshake, Error> {
+        Ok(Handshake {
+            version: self.version as u32,
+            genesis: serialize_to_bytes(&self.genesis)?,
+        })
+    }
+}
+
+impl<Id: property::BlockId> FromProtobuf<Handshake> for message::Handshake<Id> {
+    fn from_message(message: Handshake) -> Result<Self, Error> {
+        if message.version > u16::MAX as u32 {
+            return Err(Error::new(
+                Code::InvalidArgument,
+                "protocol version out of range",
+            ));
+        }
+        Ok(message::Handshake {
+            version: message.version as u16,
+            genesis: deserialize_bytes(&message.genesis)?,
+        })
+    }
+}
+
+/// write the message to the stream, prefixed by its length
+pub fn write_delimited<W, M>(mut writer: W, message: &M) -> io::Result<()>
+where
+    W: io::Write,
+    M: prost::Message,
+{
+    let mut buf = Vec::with_capacity(message.encoded_len() + 10);
+    message
+        .encode_length_delimited(&mut buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    writer.write_all(&buf)
+}
+
+/// read a message written by `write_delimited` from the stream
+///
+/// Return `None` if the stream ends before the next message. Messages
+/// longer than `max_size` bytes are rejected without being read.
+pub fn read_delimited<R, M>(mut reader: R, max_size: usize) -> io::Result<Option<M>>
+where
+    R: io::Read,
+    M: prost::Message + Default,
+{
+    let len = match read_varint(&mut reader)? {
+        None => return Ok(None),
+        Some(len) => len,
+    };
+    if len > max_size as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes exceeds the limit of {}", len, max_size),
+        ));
+    }
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf)?;
+    M::decode(&buf)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// read a varint, `None` if the stream is at its end
+fn read_varint<R: io::Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "invalid varint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_core::test_chain::Id;
+
+    #[test]
+    fn block_ids_conversion() {
+        let ids = vec![Id(1), Id(0x01020304)];
+        let message: BlockIds = ids.as_slice().into_message().unwrap();
+        assert_eq!(message.ids, vec![vec![0, 0, 0, 1], vec![1, 2, 3, 4]]);
+        assert_eq!(Vec::<Id>::from_message(message).unwrap(), ids);
+
+        let truncated = BlockIds {
+            ids: vec![vec![0, 0, 1]],
+        };
+        let err = Vec::<Id>::from_message(truncated).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        let trailing = BlockIds {
+            ids: vec![vec![0, 0, 0, 1, 0]],
+        };
+        let err = Vec::<Id>::from_message(trailing).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn handshake_conversion() {
+        let handshake = message::Handshake::new(Id(7));
+        let message: Handshake = (&handshake).into_message().unwrap();
+        assert_eq!(message.version, message::PROTOCOL_VERSION as u32);
+        assert_eq!(
+            message::Handshake::from_message(message).unwrap(),
+            handshake
+        );
+        let bad_version = Handshake {
+            version: 0x10000,
+            genesis: vec![0, 0, 0, 7],
+        };
+        assert!(message::Handshake::<Id>::from_message(bad_version).is_err());
+        assert_eq!(read_bytes::<Id>(&[0, 0, 0, 7]).unwrap(), Id(7));
+    }
+
+    #[test]
+    fn delimited_stream() {
+        let blocks = vec![
+            Block { content: vec![] },
+            Block {
+                content: vec![0xaa; 300],
+            },
+            Block {
+                content: vec![1, 2, 3],
+            },
+        ];
+        let mut stream = Vec::new();
+        for block in &blocks {
+            write_delimited(&mut stream, block).unwrap();
+        }
+        // 300 bytes of content need a 2 bytes long length
+        assert_eq!(&stream[..5], &[0x00, 0xaf, 0x02, 0x0a, 0xac]);
+
+        let mut reader = stream.as_slice();
+        let mut decoded = Vec::new();
+        while let Some(block) = read_delimited::<_, Block>(&mut reader, 1024).unwrap() {
+            decoded.push(block);
+        }
+        assert_eq!(decoded, blocks);
+
+        let err = read_delimited::<_, Block>(&stream[1..], 100).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_delimited::<_, Block>(&stream[1..10], 1024).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = read_delimited::<_, Block>(&[0x80][..], 1024).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
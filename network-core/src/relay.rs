@@ -0,0 +1,107 @@
+//! Filter of the recently relayed items.
+//!
+//! The gossip layers receive the same transactions and blocks from many
+//! peers. `RelayFilter` remembers the identifiers of the most recently
+//! seen items, so each one is only processed and propagated once.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::Hash,
+};
+
+/// Set of the identifiers of the last `capacity` items seen.
+///
+/// When full, adding a new identifier evicts the oldest one.
+#[derive(Debug, Clone)]
+pub struct RelayFilter<Id> {
+    capacity: usize,
+    seen: HashSet<Id>,
+    order: VecDeque<Id>,
+}
+
+impl<Id: Eq + Hash + Clone> RelayFilter<Id> {
+    /// create a filter remembering up to `capacity` identifiers
+    ///
+    /// # Panics
+    ///
+    /// if `capacity` is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "relay filter capacity must not be 0");
+        RelayFilter {
+            capacity,
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// return `true` if the item has not been seen recently and should be
+    /// processed and relayed; the item is remembered either way
+    pub fn should_relay(&mut self, id: &Id) -> bool {
+        if self.seen.contains(id) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(id.clone());
+        self.order.push_back(id.clone());
+        true
+    }
+
+    /// check whether the item has been seen recently, without
+    /// remembering it
+    pub fn contains(&self, id: &Id) -> bool {
+        self.seen.contains(id)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_once() {
+        let mut filter = RelayFilter::new(10);
+        assert!(filter.should_relay(&1));
+        assert!(filter.should_relay(&2));
+        assert!(!filter.should_relay(&1));
+        assert!(filter.contains(&2));
+        assert!(!filter.contains(&3));
+        assert_eq!(filter.len(), 2);
+        filter.clear();
+        assert!(filter.is_empty());
+        assert!(filter.should_relay(&1));
+    }
+
+    #[test]
+    fn evict_oldest() {
+        let mut filter = RelayFilter::new(3);
+        for id in 1..=4 {
+            assert!(filter.should_relay(&id));
+        }
+        assert_eq!(filter.len(), 3);
+        assert!(!filter.contains(&1));
+        assert!(!filter.should_relay(&2));
+        assert!(filter.should_relay(&1));
+        assert!(!filter.contains(&2));
+    }
+}
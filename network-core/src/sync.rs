@@ -0,0 +1,494 @@
+//! Header-first chain synchronization.
+//!
+//! `ChainSync` drives the synchronization of the local chain with the tip
+//! of a peer without doing any IO itself: each step returns the request
+//! to send to the peer and the caller feeds the responses back. The local
+//! blocks are accessed through a `BlockStore`, which also receives the
+//! fetched blocks.
+//!
+//! A synchronization goes through the following steps:
+//!
+//! 1. the headers from the local chain to the peer's tip are requested,
+//!    giving the peer a list of checkpoints of the local chain to start
+//!    from;
+//! 2. the received headers are checked to form a chain starting from a
+//!    block of the local store, the fork point;
+//! 3. the blocks of these headers are requested;
+//! 4. every received block is validated by the caller and written to the
+//!    store.
+//!
+//! If the peer sent fewer headers than needed to reach its tip, the
+//! process starts again from the last received block.
+
+use chain_core::{
+    property::{Block, Header},
+    store::{self, BlockStore, BlockStoreHeader, BlockStoreId},
+};
+
+use std::{collections::VecDeque, error, fmt};
+
+/// Requests to send to the peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request<Id> {
+    /// request the headers of the blocks following one of the
+    /// checkpoints, up to the given block
+    PullHeaders { from: Vec<Id>, to: Id },
+    /// request the identified blocks
+    GetBlocks(Vec<Id>),
+}
+
+/// What the caller should do after a step of the synchronization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step<Id> {
+    /// send the request to the peer and feed the response back
+    Send(Request<Id>),
+    /// wait for the remaining blocks of the last `GetBlocks` request
+    Wait,
+    /// the store contains the peer's tip
+    Done,
+}
+
+/// State of the synchronization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// `start` has not been called yet
+    Idle,
+    /// waiting for the response of a `PullHeaders` request
+    PullingHeaders,
+    /// waiting for the blocks of a `GetBlocks` request
+    FetchingBlocks,
+    /// the synchronization is complete
+    Done,
+}
+
+/// Errors of the synchronization; the synchronization with the peer
+/// should be abandoned when one is returned.
+#[derive(Debug)]
+pub enum Error<Id> {
+    /// a response was received that was not expected in the current state
+    UnexpectedResponse(State),
+    /// the peer returned no headers
+    NoHeaders,
+    /// the first header does not follow a block of the local store
+    UnknownForkPoint(Id),
+    /// the headers do not form a chain
+    DisconnectedHeaders,
+    /// the peer's chain forks from the local chain before its stable tip
+    ForkBeforeStableTip,
+    /// the peer sent another block than the next requested one
+    UnexpectedBlock { expected: Id, got: Id },
+    /// a block was rejected by the validation
+    Validation(Box<dyn error::Error + Send + Sync>),
+    /// error accessing the local store
+    Store(store::Error),
+}
+
+impl<Id: fmt::Debug> fmt::Display for Error<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnexpectedResponse(state) => {
+                write!(f, "unexpected response in state {:?}", state)
+            }
+            Error::NoHeaders => write!(f, "the peer returned no headers"),
+            Error::UnknownForkPoint(id) => {
+                write!(f, "the headers start from the unknown block {:?}", id)
+            }
+            Error::DisconnectedHeaders => write!(f, "the headers do not form a chain"),
+            Error::ForkBeforeStableTip => {
+                write!(f, "the peer's chain rolls back stable blocks")
+            }
+            Error::UnexpectedBlock { expected, got } => {
+                write!(f, "received block {:?} while expecting {:?}", got, expected)
+            }
+            Error::Validation(_) => write!(f, "invalid block"),
+            Error::Store(_) => write!(f, "block store error"),
+        }
+    }
+}
+
+impl<Id: fmt::Debug> error::Error for Error<Id> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Validation(e) => Some(e.as_ref()),
+            Error::Store(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<Id> From<store::Error> for Error<Id> {
+    fn from(e: store::Error) -> Self {
+        Error::Store(e)
+    }
+}
+
+/// identifiers of blocks of the chain ending at `tip` to send as the
+/// starting points of a `PullHeaders` request: the tip, its parent, then
+/// ancestors at exponentially increasing distances, down to the genesis
+/// block.
+pub fn checkpoints<S: BlockStore>(
+    store: &S,
+    tip: &BlockStoreId<S>,
+) -> Result<Vec<BlockStoreId<S>>, store::Error> {
+    let tip_depth = store.get_block_info(tip)?.depth;
+    let mut ids = vec![tip.clone()];
+    let mut distance = 1;
+    while distance < tip_depth {
+        ids.push(store.block_at_depth(tip, tip_depth - distance)?.block_hash);
+        distance *= 2;
+    }
+    if tip_depth > 0 {
+        ids.push(store.block_at_depth(tip, 0)?.block_hash);
+    }
+    Ok(ids)
+}
+
+/// Synchronization of the local store with the chain of a peer.
+pub struct ChainSync<S: BlockStore> {
+    state: State,
+    local_tip: BlockStoreId<S>,
+    peer_tip: BlockStoreId<S>,
+    fork_point: Option<BlockStoreId<S>>,
+    pending: VecDeque<BlockStoreId<S>>,
+    stability_depth: Option<u64>,
+}
+
+impl<S> ChainSync<S>
+where
+    S: BlockStore,
+    BlockStoreHeader<S>: Header<Id = BlockStoreId<S>>,
+{
+    /// prepare the synchronization of the chain ending at `local_tip`
+    /// with the chain ending at `peer_tip`
+    pub fn new(local_tip: BlockStoreId<S>, peer_tip: BlockStoreId<S>) -> Self {
+        ChainSync {
+            state: State::Idle,
+            local_tip,
+            peer_tip,
+            fork_point: None,
+            pending: VecDeque::new(),
+            stability_depth: None,
+        }
+    }
+
+    /// refuse to synchronize with a chain forking from the local chain
+    /// before its stable tip (see `BlockStore::stable_tip`)
+    pub fn with_stability_depth(mut self, stability_depth: u64) -> Self {
+        self.stability_depth = Some(stability_depth);
+        self
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// the last block of the local chain; it becomes the last fetched
+    /// block as the synchronization progresses
+    pub fn local_tip(&self) -> &BlockStoreId<S> {
+        &self.local_tip
+    }
+
+    pub fn peer_tip(&self) -> &BlockStoreId<S> {
+        &self.peer_tip
+    }
+
+    /// the last block shared by the local chain and the peer's chain,
+    /// known once the first headers have been received
+    pub fn fork_point(&self) -> Option<&BlockStoreId<S>> {
+        self.fork_point.as_ref()
+    }
+
+    /// start the synchronization
+    pub fn start(&mut self, store: &S) -> Result<Step<BlockStoreId<S>>, Error<BlockStoreId<S>>> {
+        if self.state != State::Idle {
+            return Err(Error::UnexpectedResponse(self.state));
+        }
+        if store.block_exists(&self.peer_tip)? {
+            self.state = State::Done;
+            return Ok(Step::Done);
+        }
+        self.pull_headers(store)
+    }
+
+    fn pull_headers(&mut self, store: &S) -> Result<Step<BlockStoreId<S>>, Error<BlockStoreId<S>>> {
+        let from = checkpoints(store, &self.local_tip)?;
+        self.state = State::PullingHeaders;
+        Ok(Step::Send(Request::PullHeaders {
+            from,
+            to: self.peer_tip.clone(),
+        }))
+    }
+
+    /// process the response to a `PullHeaders` request
+    pub fn headers_received(
+        &mut self,
+        store: &S,
+        headers: &[BlockStoreHeader<S>],
+    ) -> Result<Step<BlockStoreId<S>>, Error<BlockStoreId<S>>> {
+        if self.state != State::PullingHeaders {
+            return Err(Error::UnexpectedResponse(self.state));
+        }
+        let first = headers.first().ok_or(Error::NoHeaders)?;
+        let fork_point = first.parent_id();
+        if !store.block_exists(&fork_point)? {
+            return Err(Error::UnknownForkPoint(fork_point));
+        }
+        for (parent, header) in headers.iter().zip(headers.iter().skip(1)) {
+            if header.parent_id() != parent.id() {
+                return Err(Error::DisconnectedHeaders);
+            }
+        }
+
+        // skip the headers of the blocks already in the store, the fork
+        // point is the last of them
+        let mut fork_point = fork_point;
+        let mut missing = headers.iter();
+        let mut pending = VecDeque::new();
+        for header in &mut missing {
+            let id = header.id();
+            if store.block_exists(&id)? {
+                fork_point = id;
+            } else {
+                pending.push_back(id);
+                break;
+            }
+        }
+        pending.extend(missing.map(Header::id));
+        if self.fork_point.is_none() {
+            if let Some(stability_depth) = self.stability_depth {
+                let stable_tip = store.stable_tip(&self.local_tip, stability_depth)?;
+                if !store.is_ancestor(&stable_tip.block_hash, &fork_point)? {
+                    return Err(Error::ForkBeforeStableTip);
+                }
+            }
+            self.fork_point = Some(fork_point.clone());
+        }
+
+        if pending.is_empty() {
+            self.local_tip = fork_point;
+            return self.continue_from_local_tip(store);
+        }
+        self.pending = pending;
+        self.state = State::FetchingBlocks;
+        Ok(Step::Send(Request::GetBlocks(
+            self.pending.iter().cloned().collect(),
+        )))
+    }
+
+    /// process a block received in response to a `GetBlocks` request:
+    /// validate it and write it to the store
+    pub fn block_received<F, E>(
+        &mut self,
+        store: &mut S,
+        block: S::Block,
+        validate: F,
+    ) -> Result<Step<BlockStoreId<S>>, Error<BlockStoreId<S>>>
+    where
+        F: FnOnce(&S::Block) -> Result<(), E>,
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        if self.state != State::FetchingBlocks {
+            return Err(Error::UnexpectedResponse(self.state));
+        }
+        let id = block.id();
+        match self.pending.front() {
+            Some(expected) if expected == &id => {}
+            Some(expected) => {
+                return Err(Error::UnexpectedBlock {
+                    expected: expected.clone(),
+                    got: id,
+                })
+            }
+            None => return Err(Error::UnexpectedResponse(self.state)),
+        }
+        validate(&block).map_err(|e| Error::Validation(e.into()))?;
+        store.put_block(&block)?;
+        self.pending.pop_front();
+        self.local_tip = id;
+
+        if self.pending.is_empty() {
+            self.continue_from_local_tip(store)
+        } else {
+            Ok(Step::Wait)
+        }
+    }
+
+    fn continue_from_local_tip(
+        &mut self,
+        store: &S,
+    ) -> Result<Step<BlockStoreId<S>>, Error<BlockStoreId<S>>> {
+        if self.local_tip == self.peer_tip {
+            self.state = State::Done;
+            Ok(Step::Done)
+        } else {
+            self.pull_headers(store)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_core::{
+        store::memory::MemoryBlockStore,
+        test_chain::{linear_chain, Id, TestBlock},
+    };
+    use std::io;
+
+    type Store = MemoryBlockStore<TestBlock>;
+
+    /// chain 1 <- 2 <- ... <- n
+    fn linear_store(n: u32) -> Store {
+        let mut store = MemoryBlockStore::new();
+        for block in linear_chain(n) {
+            store.put_block(&block).unwrap();
+        }
+        store
+    }
+
+    fn no_validation(_: &TestBlock) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_of_chain() {
+        let store = linear_store(20);
+        let ids: Vec<u32> = checkpoints(&store, &Id(20))
+            .unwrap()
+            .iter()
+            .map(|id| id.0)
+            .collect();
+        assert_eq!(ids, vec![20, 19, 18, 16, 12, 4, 1]);
+        assert_eq!(checkpoints(&store, &Id(1)).unwrap(), vec![Id(1)]);
+    }
+
+    #[test]
+    fn sync_fork() {
+        // local 1 <- 2 <- 3, peer 2 <- 4 <- 5 <- 6
+        let mut store = linear_store(3);
+        let mut sync = ChainSync::<Store>::new(Id(3), Id(6));
+        assert_eq!(
+            sync.start(&store).unwrap(),
+            Step::Send(Request::PullHeaders {
+                from: vec![Id(3), Id(2), Id(1)],
+                to: Id(6)
+            })
+        );
+
+        // the peer starts from the genesis block it has in common
+        let headers = [
+            TestBlock(2, 1, 1),
+            TestBlock(4, 2, 2),
+            TestBlock(5, 4, 4),
+            TestBlock(6, 5, 5),
+        ];
+        assert_eq!(
+            sync.headers_received(&store, &headers).unwrap(),
+            Step::Send(Request::GetBlocks(vec![Id(4), Id(5), Id(6)]))
+        );
+        assert_eq!(sync.fork_point(), Some(&Id(2)));
+        assert_eq!(sync.state(), State::FetchingBlocks);
+
+        for block in &headers[1..3] {
+            assert_eq!(
+                sync.block_received(&mut store, *block, no_validation)
+                    .unwrap(),
+                Step::Wait
+            );
+        }
+        assert_eq!(
+            sync.block_received(&mut store, TestBlock(6, 5, 5), no_validation)
+                .unwrap(),
+            Step::Done
+        );
+        assert_eq!(sync.local_tip(), &Id(6));
+        assert!(store.is_ancestor(&Id(2), &Id(6)).unwrap());
+    }
+
+    #[test]
+    fn sync_in_batches() {
+        let mut store = linear_store(2);
+        let mut sync = ChainSync::<Store>::new(Id(2), Id(4));
+        sync.start(&store).unwrap();
+        assert_eq!(
+            sync.headers_received(&store, &[TestBlock(3, 2, 2)])
+                .unwrap(),
+            Step::Send(Request::GetBlocks(vec![Id(3)]))
+        );
+        assert_eq!(
+            sync.block_received(&mut store, TestBlock(3, 2, 2), no_validation)
+                .unwrap(),
+            Step::Send(Request::PullHeaders {
+                from: vec![Id(3), Id(2), Id(1)],
+                to: Id(4)
+            })
+        );
+        sync.headers_received(&store, &[TestBlock(4, 3, 3)])
+            .unwrap();
+        sync.block_received(&mut store, TestBlock(4, 3, 3), no_validation)
+            .unwrap();
+        assert_eq!(sync.state(), State::Done);
+        assert_eq!(sync.fork_point(), Some(&Id(2)));
+
+        let mut sync = ChainSync::<Store>::new(Id(4), Id(3));
+        assert_eq!(sync.start(&store).unwrap(), Step::Done);
+    }
+
+    #[test]
+    fn sync_stable_chain() {
+        // local 1 <- 2 <- 3 <- 4, peer 2 <- 5
+        let store = linear_store(4);
+        let mut sync = ChainSync::<Store>::new(Id(4), Id(5)).with_stability_depth(2);
+        sync.start(&store).unwrap();
+        assert_eq!(
+            sync.headers_received(&store, &[TestBlock(5, 2, 2)])
+                .unwrap(),
+            Step::Send(Request::GetBlocks(vec![Id(5)]))
+        );
+
+        let mut sync = ChainSync::<Store>::new(Id(4), Id(5)).with_stability_depth(1);
+        sync.start(&store).unwrap();
+        assert!(matches!(
+            sync.headers_received(&store, &[TestBlock(5, 2, 2)]),
+            Err(Error::ForkBeforeStableTip)
+        ));
+    }
+
+    #[test]
+    fn sync_errors() {
+        let mut store = linear_store(2);
+        let mut sync = ChainSync::<Store>::new(Id(2), Id(5));
+        assert!(matches!(
+            sync.headers_received(&store, &[TestBlock(3, 2, 2)]),
+            Err(Error::UnexpectedResponse(State::Idle))
+        ));
+        sync.start(&store).unwrap();
+        assert!(matches!(
+            sync.headers_received(&store, &[]),
+            Err(Error::NoHeaders)
+        ));
+        assert!(matches!(
+            sync.headers_received(&store, &[TestBlock(4, 3, 3)]),
+            Err(Error::UnknownForkPoint(Id(3)))
+        ));
+        assert!(matches!(
+            sync.headers_received(&store, &[TestBlock(3, 2, 2), TestBlock(5, 4, 4)]),
+            Err(Error::DisconnectedHeaders)
+        ));
+
+        sync.headers_received(&store, &[TestBlock(3, 2, 2), TestBlock(5, 3, 3)])
+            .unwrap();
+        assert!(matches!(
+            sync.block_received(&mut store, TestBlock(5, 3, 3), no_validation),
+            Err(Error::UnexpectedBlock {
+                expected: Id(3),
+                got: Id(5)
+            })
+        ));
+        assert!(matches!(
+            sync.block_received(&mut store, TestBlock(3, 2, 2), |_| Err("invalid")),
+            Err(Error::Validation(_))
+        ));
+        assert!(!store.block_exists(&Id(3)).unwrap());
+    }
+}